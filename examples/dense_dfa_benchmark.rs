@@ -0,0 +1,32 @@
+//! Compare how long a DFA takes to run on a megabyte of text when its
+//! transitions are looked up in a hash map and when they are read out of a
+//! table.
+//!
+//! Run with `cargo run --release --example dense_dfa_benchmark`.
+
+use std::time::Instant;
+
+fn main() {
+    let pattern = "(a|b)*abb(a|b)*c";
+    let input = "ab".repeat(500_000) + "abbc";
+
+    let dfa = regex::compile_to_dfa(pattern).unwrap();
+    let start = Instant::now();
+    let dense = dfa.to_dense();
+    let dense_build = start.elapsed();
+
+    let start = Instant::now();
+    let dfa_matched = dfa.run(&input);
+    let dfa_run = start.elapsed();
+    let start = Instant::now();
+    let dense_matched = dense.run_dense(&input);
+    let dense_run = start.elapsed();
+
+    assert_eq!(dfa_matched, dense_matched);
+    println!("pattern {:?} on {} characters", pattern, input.len());
+    println!("hash map DFA: ran in {:?}", dfa_run);
+    println!(
+        "dense DFA: built in {:?}, ran in {:?}",
+        dense_build, dense_run
+    );
+}
@@ -0,0 +1,30 @@
+//! Compare how long the NFA and the DFA compiled from the same pattern take
+//! to run on a long input.
+//!
+//! Run with `cargo run --release --example dfa_benchmark`.
+
+use std::time::Instant;
+
+fn main() {
+    let pattern = "(a|b)*abb(a|b)*c";
+    let input = "ab".repeat(50_000) + "abbc";
+
+    let start = Instant::now();
+    let nfa = regex::compile(pattern);
+    let nfa_compile = start.elapsed();
+    let start = Instant::now();
+    let nfa_matched = nfa.run(&input);
+    let nfa_run = start.elapsed();
+
+    let start = Instant::now();
+    let dfa = regex::compile_to_dfa(pattern).unwrap();
+    let dfa_compile = start.elapsed();
+    let start = Instant::now();
+    let dfa_matched = dfa.run(&input);
+    let dfa_run = start.elapsed();
+
+    assert_eq!(nfa_matched, dfa_matched);
+    println!("pattern {:?} on {} characters", pattern, input.len());
+    println!("NFA: compiled in {:?}, ran in {:?}", nfa_compile, nfa_run);
+    println!("DFA: compiled in {:?}, ran in {:?}", dfa_compile, dfa_run);
+}
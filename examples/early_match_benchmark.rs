@@ -0,0 +1,24 @@
+//! Time how long matching takes on a long input when the match comes early,
+//! compared to when the pattern is anchored to the end and the whole input
+//! has to be read.
+//!
+//! Run with `cargo run --release --example early_match_benchmark`.
+
+use std::time::Instant;
+
+fn main() {
+    let input = "needle".to_string() + &"x".repeat(10_000_000);
+
+    for pattern in &["needle", "needle.*x$"] {
+        let regex = regex::Regex::new(pattern).unwrap();
+        let start = Instant::now();
+        let matched = regex.is_match(&input);
+        println!(
+            "pattern {:?} on {} characters: {} in {:?}",
+            pattern,
+            input.len(),
+            matched,
+            start.elapsed()
+        );
+    }
+}
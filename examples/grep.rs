@@ -0,0 +1,64 @@
+//! Print the lines of a file, or of stdin if no file is given, that match a
+//! pattern, with each match highlighted.
+//!
+//! Run with `cargo run --example grep 'a.*b' file.txt`.
+
+use regex::Regex;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process;
+
+/// Wrap each match in `line` in the terminal codes for bold red.
+fn highlight(regex: &Regex, line: &str) -> String {
+    let mut highlighted = String::new();
+    let mut last_end = 0;
+    for (start, end) in regex.find_iter(line) {
+        highlighted.push_str(&line[last_end..start]);
+        highlighted.push_str("\x1b[1;31m");
+        highlighted.push_str(&line[start..end]);
+        highlighted.push_str("\x1b[0m");
+        last_end = end;
+    }
+    highlighted.push_str(&line[last_end..]);
+    highlighted
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("usage: {} PATTERN [FILE]", args[0]);
+        process::exit(2);
+    }
+
+    let regex = Regex::new(&args[1]).unwrap_or_else(|error| {
+        eprintln!("invalid pattern {:?}: {}", args[1], error);
+        process::exit(2);
+    });
+    let input: Box<dyn BufRead> = match args.get(2) {
+        Some(path) => Box::new(BufReader::new(File::open(path).unwrap_or_else(|error| {
+            eprintln!("can't open {}: {}", path, error);
+            process::exit(2);
+        }))),
+        None => Box::new(BufReader::new(io::stdin())),
+    };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut matched = false;
+    for line in input.lines() {
+        let line = line.unwrap_or_else(|error| {
+            eprintln!("can't read input: {}", error);
+            process::exit(2);
+        });
+        if regex.is_match(&line) {
+            matched = true;
+            writeln!(out, "{}", highlight(&regex, &line)).unwrap();
+        }
+    }
+
+    // like grep, exit with 1 if no line matched
+    if !matched {
+        process::exit(1);
+    }
+}
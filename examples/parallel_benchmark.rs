@@ -0,0 +1,26 @@
+//! Compare running an NFA with many states open at once on one thread
+//! and on several.
+//!
+//! Run with `cargo run --release --features parallel --example parallel_benchmark`.
+
+use std::time::Instant;
+
+fn main() {
+    // every `a` read starts another path through the counted repetition
+    let pattern = "(?:a|b)*a(?:a|b){500}c";
+    let input = "ab".repeat(5_000);
+    let nfa = regex::compile(pattern);
+
+    let start = Instant::now();
+    let matched = nfa.run(&input);
+    let sequential = start.elapsed();
+
+    let start = Instant::now();
+    let parallel_matched = nfa.run_parallel(&input);
+    let parallel = start.elapsed();
+
+    assert_eq!(matched, parallel_matched);
+    println!("pattern {:?} on {} characters", pattern, input.len());
+    println!("run:          {:?}", sequential);
+    println!("run_parallel: {:?}", parallel);
+}
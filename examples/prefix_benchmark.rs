@@ -0,0 +1,24 @@
+//! Time how long searching a long input takes for a pattern that starts with
+//! a literal prefix, which is skipped to with a substring search, compared to
+//! the same pattern written so that it has no literal prefix.
+//!
+//! Run with `cargo run --release --example prefix_benchmark`.
+
+use std::time::Instant;
+
+fn main() {
+    let input = "hay ".repeat(2_500_000) + "needle42";
+
+    for pattern in &["needle\\d+", "(?:needle)\\d+"] {
+        let regex = regex::Regex::new(pattern).unwrap();
+        let start = Instant::now();
+        let found = regex.find(&input);
+        println!(
+            "pattern {:?} on {} characters: {:?} in {:?}",
+            pattern,
+            input.len(),
+            found,
+            start.elapsed()
+        );
+    }
+}
@@ -0,0 +1,71 @@
+/// Read-only access to the states and transitions of a compiled automaton,
+/// for running it some other way than the methods it comes with.
+///
+/// States are numbered, with reading starting from state 0. Running an
+/// automaton with this trait means keeping track of the current states,
+/// starting from the epsilon closure of state 0:
+///
+/// * before reading each character, replace the current states with their
+///   `epsilon_closure`, given the characters either side of the position;
+/// * then `step` each of them on the character;
+/// * after the last character, take the `epsilon_closure` once more, and
+///   accept if any of the states `is_accepting`.
+///
+/// `step` never follows epsilon transitions itself, since whether the
+/// transitions for assertions like `\b` can be taken depends on the
+/// character after the position as well as the one before it.
+pub trait Automaton {
+    /// Find every state reached from `state` by reading `c`.
+    fn step(&self, state: u32, c: char) -> Vec<u32>;
+
+    /// Find `state` and every state reached from it without reading anything,
+    /// at a position with `before` just before it and `after` just after it.
+    /// Either is `None` at the start or end of the input.
+    fn epsilon_closure(&self, state: u32, before: Option<char>, after: Option<char>) -> Vec<u32>;
+
+    /// Returns true if `state` is an accepting state.
+    fn is_accepting(&self, state: u32) -> bool;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// Run `automaton` on `input` the way the trait documents, keeping the
+    /// current states in a sorted set.
+    fn run(automaton: &impl Automaton, input: &str) -> bool {
+        let mut states = BTreeSet::new();
+        states.insert(0);
+        let mut before = None;
+        for c in input.chars() {
+            let closed: BTreeSet<u32> = states
+                .iter()
+                .flat_map(|state| automaton.epsilon_closure(*state, before, Some(c)))
+                .collect();
+            states = closed
+                .iter()
+                .flat_map(|state| automaton.step(*state, c))
+                .collect();
+            before = Some(c);
+        }
+        states
+            .iter()
+            .flat_map(|state| automaton.epsilon_closure(*state, before, None))
+            .any(|state| automaton.is_accepting(state))
+    }
+
+    #[test]
+    fn run_through_trait() {
+        let inputs = ["", "ab", "a b", "xab c", "abab", "ba", "ab\nc"];
+        for pattern in &["^(a|b)*b$", "\\bab\\b", "a|^b", "^$", "b.c"] {
+            let nfa = crate::compile(pattern);
+            let dfa = nfa.to_dfa();
+            for input in &inputs {
+                let expected = nfa.run(input);
+                assert_eq!(run(&nfa, input), expected, "{:?} on {:?}", pattern, input);
+                assert_eq!(run(&dfa, input), expected, "{:?} on {:?}", pattern, input);
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A major Unicode general category, written `\p{L}` and so on.
+///
+/// Characters are sorted into categories using the `char` methods from the
+/// standard library, with a few ranges for the categories it has no method
+/// for. This covers the common characters of each category but doesn't follow
+/// the Unicode database exactly: unassigned characters aren't in any category,
+/// and marks, punctuation and symbols outside the listed ranges are missed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Category {
+    /// Letters, written `L`.
+    Letter,
+    /// Combining marks, written `M`.
+    Mark,
+    /// Numbers, written `N`.
+    Number,
+    /// Punctuation, written `P`.
+    Punctuation,
+    /// Symbols, written `S`.
+    Symbol,
+    /// Separators such as spaces, written `Z`.
+    Separator,
+    /// Control, format and private use characters, written `C`.
+    Other,
+}
+
+/// Combining marks, from the combining diacritical mark blocks.
+const MARKS: &[(char, char)] = &[
+    ('\u{0300}', '\u{036F}'),
+    ('\u{0483}', '\u{0489}'),
+    ('\u{1AB0}', '\u{1AFF}'),
+    ('\u{1DC0}', '\u{1DFF}'),
+    ('\u{20D0}', '\u{20FF}'),
+    ('\u{FE00}', '\u{FE0F}'),
+    ('\u{FE20}', '\u{FE2F}'),
+];
+
+/// Punctuation outside of ASCII.
+const PUNCTUATION: &[(char, char)] = &[
+    ('\u{00A1}', '\u{00A1}'),
+    ('\u{00A7}', '\u{00A7}'),
+    ('\u{00AB}', '\u{00AB}'),
+    ('\u{00B6}', '\u{00B7}'),
+    ('\u{00BB}', '\u{00BB}'),
+    ('\u{00BF}', '\u{00BF}'),
+    ('\u{2010}', '\u{2027}'),
+    ('\u{2030}', '\u{205E}'),
+    ('\u{3001}', '\u{3003}'),
+    ('\u{3008}', '\u{3011}'),
+];
+
+/// Symbols outside of ASCII.
+const SYMBOLS: &[(char, char)] = &[
+    ('\u{00A2}', '\u{00A6}'),
+    ('\u{00A8}', '\u{00A9}'),
+    ('\u{00AC}', '\u{00AC}'),
+    ('\u{00AE}', '\u{00B1}'),
+    ('\u{00B4}', '\u{00B4}'),
+    ('\u{00B8}', '\u{00B8}'),
+    ('\u{00D7}', '\u{00D7}'),
+    ('\u{00F7}', '\u{00F7}'),
+    ('\u{20A0}', '\u{20C0}'),
+    ('\u{2190}', '\u{23FF}'),
+    ('\u{2500}', '\u{27BF}'),
+    ('\u{2B00}', '\u{2BFF}'),
+    ('\u{1F300}', '\u{1FAFF}'),
+];
+
+/// Characters that aren't control characters but are still in `C`.
+const OTHER: &[(char, char)] = &[
+    ('\u{00AD}', '\u{00AD}'),
+    ('\u{200B}', '\u{200F}'),
+    ('\u{202A}', '\u{202E}'),
+    ('\u{2060}', '\u{2064}'),
+    ('\u{FEFF}', '\u{FEFF}'),
+    ('\u{E000}', '\u{F8FF}'),
+    ('\u{F0000}', '\u{10FFFF}'),
+];
+
+fn in_ranges(ranges: &[(char, char)], c: char) -> bool {
+    ranges.iter().any(|(low, high)| (*low..=*high).contains(&c))
+}
+
+impl Category {
+    /// Find the category written as `letter`.
+    pub fn from_letter(letter: char) -> Option<Category> {
+        match letter {
+            'L' => Some(Category::Letter),
+            'M' => Some(Category::Mark),
+            'N' => Some(Category::Number),
+            'P' => Some(Category::Punctuation),
+            'S' => Some(Category::Symbol),
+            'Z' => Some(Category::Separator),
+            'C' => Some(Category::Other),
+            _ => None,
+        }
+    }
+
+    /// The letter the category is written as.
+    pub fn letter(&self) -> char {
+        match self {
+            Category::Letter => 'L',
+            Category::Mark => 'M',
+            Category::Number => 'N',
+            Category::Punctuation => 'P',
+            Category::Symbol => 'S',
+            Category::Separator => 'Z',
+            Category::Other => 'C',
+        }
+    }
+
+    /// Find the category of `c`, if it is in one.
+    fn of(c: char) -> Option<Category> {
+        // checked in this order since, for example, some marks are also
+        // alphabetic and some letters are also numeric
+        if c.is_control() || in_ranges(OTHER, c) {
+            Some(Category::Other)
+        } else if c.is_whitespace() {
+            Some(Category::Separator)
+        } else if in_ranges(MARKS, c) {
+            Some(Category::Mark)
+        } else if c.is_numeric() {
+            Some(Category::Number)
+        } else if c.is_alphabetic() {
+            Some(Category::Letter)
+        } else if "$+<=>^`|~".contains(c) || in_ranges(SYMBOLS, c) {
+            Some(Category::Symbol)
+        } else if c.is_ascii_punctuation() || in_ranges(PUNCTUATION, c) {
+            Some(Category::Punctuation)
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if `c` is in the category.
+    pub fn contains(&self, c: char) -> bool {
+        Category::of(c) == Some(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn categories() {
+        for (c, category) in &[
+            ('a', Category::Letter),
+            ('é', Category::Letter),
+            ('ж', Category::Letter),
+            ('中', Category::Letter),
+            ('\u{0301}', Category::Mark),
+            ('7', Category::Number),
+            ('٣', Category::Number),
+            ('Ⅻ', Category::Number),
+            ('!', Category::Punctuation),
+            ('_', Category::Punctuation),
+            ('«', Category::Punctuation),
+            ('—', Category::Punctuation),
+            ('+', Category::Symbol),
+            ('€', Category::Symbol),
+            ('→', Category::Symbol),
+            (' ', Category::Separator),
+            ('\u{3000}', Category::Separator),
+            ('\n', Category::Other),
+            ('\u{200B}', Category::Other),
+        ] {
+            assert!(category.contains(*c), "{:?}", c);
+            assert!(Category::from_letter(category.letter()) == Some(*category));
+        }
+        assert!(Category::of('\u{0378}').is_none());
+        assert!(Category::from_letter('X').is_none());
+    }
+}
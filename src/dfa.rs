@@ -0,0 +1,799 @@
+use crate::automaton::Automaton;
+use crate::dot;
+use crate::nfa::{NFAChar, Nfa, Transition};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// Represents a deterministic finite automaton
+/// # Q: States
+/// We don't need to explicitly store this.
+/// # q0: Start state
+/// Always defined as 0.
+/// # Σ: Alphabet
+/// Defined as being all unicode characters, split into numbered classes of
+/// characters that are always read the same way.
+#[derive(Clone)]
+pub struct Dfa {
+    /// # δ: Transition Function
+    /// Specifies a state and its input to get the output state.
+    /// Uses `Some(x)` to represent transition on every character in class `x`,
+    /// and `None` to represent transition on everything that has no `Some(x)`
+    /// transition from the same state.
+    transitions: HashMap<(u32, Option<u32>), u32>,
+    /// The characters in each class, as `(low, high, class)` ranges sorted by
+    /// `low`. Characters outside of them are in no class, so only `None`
+    /// transitions read them.
+    ranges: Vec<(char, char, u32)>,
+    /// # F: Accepting states
+    /// Set of states that the DFA will accept in.
+    accepting_states: HashSet<u32>,
+    /// The states that no accepting state can be reached from, so once the
+    /// DFA is in one, it can't accept whatever comes next.
+    dead_states: HashSet<u32>,
+}
+
+impl Dfa {
+    /// Create a new DFA
+    ///
+    /// Give a transition function in the form of a `HashMap`.
+    /// Use `Some(x)` as the input character to add transition on `x`.
+    /// Use `None` as the input character to add transition on everything else.
+    ///
+    /// Give the set of accepting states in the form of a `HashSet`.
+    /// Refer to states by an integer.
+    pub fn new(
+        transitions: HashMap<(u32, Option<char>), u32>,
+        accepting_states: HashSet<u32>,
+    ) -> Dfa {
+        // each character read on its own is a class of its own
+        let chars: BTreeSet<char> = transitions.keys().filter_map(|(_, input)| *input).collect();
+        let ranges: Vec<(char, char, u32)> = chars
+            .iter()
+            .enumerate()
+            .map(|(class, c)| (*c, *c, class as u32))
+            .collect();
+        let transitions = transitions
+            .into_iter()
+            .map(|((from, input), to)| ((from, input.and_then(|c| class_of(&ranges, c))), to))
+            .collect();
+        Dfa::from_classes(transitions, ranges, accepting_states)
+    }
+
+    /// Create a DFA whose `Some(x)` transitions read every character in class
+    /// `x`, given as `(low, high, x)` ranges of characters that don't overlap.
+    pub(crate) fn from_classes(
+        transitions: HashMap<(u32, Option<u32>), u32>,
+        mut ranges: Vec<(char, char, u32)>,
+        accepting_states: HashSet<u32>,
+    ) -> Dfa {
+        ranges.sort_unstable();
+        let dead_states = dead_states(&transitions, &accepting_states);
+        Dfa {
+            transitions,
+            ranges,
+            accepting_states,
+            dead_states,
+        }
+    }
+
+    /// Find where `state` goes on `c`, if anywhere.
+    fn next_state(&self, state: u32, c: char) -> Option<u32> {
+        class_of(&self.ranges, c)
+            .and_then(|class| self.transitions.get(&(state, Some(class))))
+            .or_else(|| self.transitions.get(&(state, None)))
+            .copied()
+    }
+
+    /// The ranges of characters in each class.
+    fn class_ranges(&self) -> HashMap<u32, Vec<(char, char)>> {
+        let mut classes: HashMap<u32, Vec<(char, char)>> = HashMap::new();
+        for (low, high, class) in &self.ranges {
+            classes.entry(*class).or_default().push((*low, *high));
+        }
+        classes
+    }
+
+    /// Run the DFA
+    ///
+    /// give a unicode string as an input.
+    /// return true if the DFA accepts.
+    /// return false if the DFA rejects.
+    pub fn run(&self, input: &str) -> bool {
+        let mut state = 0;
+        for c in input.chars() {
+            // exit if there was no valid transition
+            match self.next_state(state, c) {
+                Some(next_state) => state = next_state,
+                None => return false,
+            }
+            // or if there is no way to accept from here
+            if self.dead_states.contains(&state) {
+                return false;
+            }
+        }
+
+        self.accepting_states.contains(&state)
+    }
+
+    /// Write the DFA as a Graphviz `digraph`, labelling `None` transitions `else`
+    /// and the others with the characters in their class.
+    pub fn to_dot(&self) -> String {
+        let classes = self.class_ranges();
+        let edges = self
+            .transitions
+            .iter()
+            .map(|((from, input), to)| {
+                let label = match input.map(|class| &classes[&class][..]) {
+                    None => String::from("else"),
+                    Some([(low, high)]) if low == high => dot::char_label(*low),
+                    Some(ranges) => format!("[{}]", dot::ranges_label(ranges)),
+                };
+                (*from, *to, label)
+            })
+            .collect();
+        dot::digraph(BTreeSet::new(), &self.accepting_states, edges)
+    }
+
+    /// Build the NFA with the same states and transitions, which accepts
+    /// the same input.
+    ///
+    /// Each `Some(x)` transition becomes an `If` or a `Range` for each range
+    /// of characters in class `x`, and each `None` an `Else`, so the NFA is
+    /// only ever in one state at a time.
+    pub fn to_nfa(&self) -> Nfa {
+        let classes = self.class_ranges();
+        let mut transitions = Transition::new();
+        for ((from, input), to) in &self.transitions {
+            let inputs = match input {
+                None => vec![NFAChar::Else],
+                Some(class) => classes[class]
+                    .iter()
+                    .map(|(low, high)| {
+                        if low == high {
+                            NFAChar::If(*low)
+                        } else {
+                            NFAChar::Range(*low, *high)
+                        }
+                    })
+                    .collect(),
+            };
+            for input in inputs {
+                transitions.insert((*from, input), vec![*to]);
+            }
+        }
+        Nfa::new(transitions, self.accepting_states.clone())
+    }
+
+    /// Store the DFA as a table indexed by state and character, which is
+    /// faster to run but takes 1KiB for each state.
+    pub fn to_dense(&self) -> DenseDfa {
+        // number the states from 0, keeping the start state first
+        let mut states: BTreeSet<u32> = self.accepting_states.iter().copied().collect();
+        states.insert(0);
+        for ((from, _), to) in &self.transitions {
+            states.insert(*from);
+            states.insert(*to);
+        }
+        let index: HashMap<u32, u32> = states
+            .iter()
+            .enumerate()
+            .map(|(i, state)| (*state, i as u32))
+            .collect();
+
+        // moving into a dead state is as good as having no transition
+        let target = |to: &u32| {
+            if self.dead_states.contains(to) {
+                DEAD
+            } else {
+                index[to]
+            }
+        };
+        let otherwise: Vec<u32> = states
+            .iter()
+            .map(|state| self.transitions.get(&(*state, None)).map_or(DEAD, target))
+            .collect();
+        let table: Vec<[u32; 256]> = states
+            .iter()
+            .map(|state| {
+                let mut row = [DEAD; 256];
+                for (c, to) in row.iter_mut().enumerate() {
+                    *to = self
+                        .next_state(*state, char::from(c as u8))
+                        .map_or(DEAD, |to| target(&to));
+                }
+                row
+            })
+            .collect();
+        let wide = self
+            .transitions
+            .iter()
+            .filter_map(|((from, input), to)| Some(((index[from], (*input)?), target(to))))
+            .collect();
+
+        DenseDfa {
+            table,
+            ranges: self
+                .ranges
+                .iter()
+                .filter(|(_, high, _)| *high as u32 >= 256)
+                .copied()
+                .collect(),
+            wide,
+            otherwise,
+            accepting: states
+                .iter()
+                .map(|state| self.accepting_states.contains(state))
+                .collect(),
+        }
+    }
+
+    /// Build the DFA with the fewest states that accepts the same input,
+    /// using Hopcroft's partition refinement.
+    ///
+    /// Having no transition on a character is treated as moving to a dead
+    /// state that can never accept, so any states that can never accept are
+    /// merged into it and left out.
+    pub fn minimize(&self) -> Dfa {
+        // index every state, with the dead state last
+        let mut states: BTreeSet<u32> = self.accepting_states.iter().copied().collect();
+        states.insert(0);
+        for ((from, _), to) in &self.transitions {
+            states.insert(*from);
+            states.insert(*to);
+        }
+        let states: Vec<u32> = states.into_iter().collect();
+        let index: HashMap<u32, usize> = states.iter().enumerate().map(|(i, s)| (*s, i)).collect();
+        let dead = states.len();
+
+        // `None` stands for every class without a transition of its own
+        let mut symbols: BTreeSet<Option<u32>> =
+            self.transitions.keys().map(|(_, input)| *input).collect();
+        symbols.insert(None);
+        let symbols: Vec<Option<u32>> = symbols.into_iter().collect();
+
+        // where each state goes on each symbol, and where it came from
+        let target = |state: usize, symbol: Option<u32>| -> usize {
+            if state == dead {
+                return dead;
+            }
+            let from = states[state];
+            self.transitions
+                .get(&(from, symbol))
+                .or_else(|| self.transitions.get(&(from, None)))
+                .map_or(dead, |to| index[to])
+        };
+        let targets: Vec<Vec<usize>> = (0..=dead)
+            .map(|state| {
+                symbols
+                    .iter()
+                    .map(|symbol| target(state, *symbol))
+                    .collect()
+            })
+            .collect();
+        let mut sources = vec![vec![Vec::new(); dead + 1]; symbols.len()];
+        for (state, to) in targets.iter().enumerate() {
+            for (symbol, to) in to.iter().enumerate() {
+                sources[symbol][*to].push(state);
+            }
+        }
+
+        // start by splitting the accepting states from the rest
+        let accepting: Vec<bool> = (0..=dead)
+            .map(|state| state != dead && self.accepting_states.contains(&states[state]))
+            .collect();
+        let mut blocks: Vec<Vec<usize>> = vec![
+            (0..=dead).filter(|s| accepting[*s]).collect(),
+            (0..=dead).filter(|s| !accepting[*s]).collect(),
+        ];
+        blocks.retain(|block| !block.is_empty());
+        let mut block_of = vec![0; dead + 1];
+        for (b, block) in blocks.iter().enumerate() {
+            for state in block {
+                block_of[*state] = b;
+            }
+        }
+        let mut waiting: Vec<usize> = (0..blocks.len()).collect();
+        let mut in_waiting = vec![true; blocks.len()];
+
+        while let Some(splitter) = waiting.pop() {
+            in_waiting[splitter] = false;
+            let splitter_states = blocks[splitter].clone();
+            for symbol_sources in &sources {
+                // every state that moves into the splitter on this symbol
+                let mut moving = HashSet::new();
+                for to in &splitter_states {
+                    moving.extend(symbol_sources[*to].iter().copied());
+                }
+
+                let touched: BTreeSet<usize> = moving.iter().map(|s| block_of[*s]).collect();
+                for b in touched {
+                    let (inside, outside): (Vec<usize>, Vec<usize>) =
+                        blocks[b].iter().partition(|s| moving.contains(s));
+                    if outside.is_empty() {
+                        continue;
+                    }
+
+                    // split the block, giving the new block the states outside
+                    let new_block = blocks.len();
+                    for state in &outside {
+                        block_of[*state] = new_block;
+                    }
+                    let smaller = if inside.len() <= outside.len() {
+                        b
+                    } else {
+                        new_block
+                    };
+                    blocks[b] = inside;
+                    blocks.push(outside);
+                    in_waiting.push(false);
+
+                    if in_waiting[b] {
+                        waiting.push(new_block);
+                        in_waiting[new_block] = true;
+                    } else {
+                        waiting.push(smaller);
+                        in_waiting[smaller] = true;
+                    }
+                }
+            }
+        }
+
+        // number the blocks reachable from the start, leaving the dead block
+        // out unless some character has to be sent there explicitly
+        let none = symbols.iter().position(|symbol| symbol.is_none()).unwrap();
+        let dead_block = block_of[dead];
+        let mut ids = HashMap::new();
+        ids.insert(block_of[index[&0]], 0);
+        let mut order = vec![block_of[index[&0]]];
+        let mut transitions = HashMap::new();
+        let mut next = 0;
+        while next < order.len() {
+            let block = order[next];
+            let from = next as u32;
+            next += 1;
+
+            let representative = blocks[block][0];
+            let otherwise = block_of[targets[representative][none]];
+            for (symbol, to) in symbols.iter().zip(&targets[representative]) {
+                let to = block_of[*to];
+                let needed = match symbol {
+                    None => to != dead_block,
+                    Some(_) => to != otherwise,
+                };
+                if needed {
+                    let id = *ids.entry(to).or_insert_with(|| {
+                        order.push(to);
+                        order.len() as u32 - 1
+                    });
+                    transitions.insert((from, *symbol), id);
+                }
+            }
+        }
+
+        let accepting_states = ids
+            .iter()
+            .filter(|(block, _)| accepting[blocks[**block][0]])
+            .map(|(_, id)| *id)
+            .collect();
+
+        Dfa::from_classes(transitions, self.ranges.clone(), accepting_states)
+    }
+}
+
+/// A DFA has no epsilon transitions, so the epsilon closure of a state is
+/// only the state itself.
+impl Automaton for Dfa {
+    fn step(&self, state: u32, c: char) -> Vec<u32> {
+        self.next_state(state, c).into_iter().collect()
+    }
+
+    fn epsilon_closure(&self, state: u32, _: Option<char>, _: Option<char>) -> Vec<u32> {
+        vec![state]
+    }
+
+    fn is_accepting(&self, state: u32) -> bool {
+        self.accepting_states.contains(&state)
+    }
+}
+
+/// Find the class of `c` in `ranges`, which are sorted by their first character.
+fn class_of(ranges: &[(char, char, u32)], c: char) -> Option<u32> {
+    let after = ranges.partition_point(|(low, _, _)| *low <= c);
+    let (_, high, class) = ranges.get(after.checked_sub(1)?)?;
+    if c <= *high {
+        Some(*class)
+    } else {
+        None
+    }
+}
+
+/// Find the states that no accepting state can be reached from.
+fn dead_states<T>(
+    transitions: &HashMap<(u32, T), u32>,
+    accepting_states: &HashSet<u32>,
+) -> HashSet<u32> {
+    let mut sources: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut states: HashSet<u32> = accepting_states.iter().copied().collect();
+    states.insert(0);
+    for ((from, _), to) in transitions {
+        sources.entry(*to).or_default().push(*from);
+        states.insert(*from);
+        states.insert(*to);
+    }
+
+    // work backwards from the accepting states
+    let mut live: HashSet<u32> = accepting_states.iter().copied().collect();
+    let mut stack: Vec<u32> = live.iter().copied().collect();
+    while let Some(state) = stack.pop() {
+        for from in sources.get(&state).into_iter().flatten() {
+            if live.insert(*from) {
+                stack.push(*from);
+            }
+        }
+    }
+    states.retain(|state| !live.contains(state));
+    states
+}
+
+/// Marks a missing transition in a `DenseDfa`.
+const DEAD: u32 = u32::MAX;
+
+/// A DFA stored as a table with a row for each state, created by
+/// `Dfa::to_dense`.
+///
+/// The transitions on characters below 256 are read straight out of the
+/// row, so running it takes no hashing on ASCII and Latin-1 text. Other
+/// characters are looked up like in a `Dfa`.
+#[derive(Clone)]
+pub struct DenseDfa {
+    /// Where each state goes on each character below 256.
+    table: Vec<[u32; 256]>,
+    /// The ranges of the `Dfa`'s classes that reach 256 or above.
+    ranges: Vec<(char, char, u32)>,
+    /// Where each state goes on the classes that it has a transition of its
+    /// own on.
+    wide: HashMap<(u32, u32), u32>,
+    /// Where each state goes on every other character from 256 up.
+    otherwise: Vec<u32>,
+    /// Whether each state accepts.
+    accepting: Vec<bool>,
+}
+
+impl DenseDfa {
+    /// Run the DFA, accepting exactly the same input as `Dfa::run`.
+    pub fn run_dense(&self, input: &str) -> bool {
+        let bytes = input.as_bytes();
+        let mut state = 0;
+        let mut i = 0;
+        while i < bytes.len() {
+            let next_state = if bytes[i].is_ascii() {
+                i += 1;
+                self.table[state][bytes[i - 1] as usize]
+            } else {
+                let c = input[i..].chars().next().unwrap();
+                i += c.len_utf8();
+                if (c as u32) < 256 {
+                    self.table[state][c as usize]
+                } else {
+                    class_of(&self.ranges, c)
+                        .and_then(|class| self.wide.get(&(state as u32, class)))
+                        .copied()
+                        .unwrap_or(self.otherwise[state])
+                }
+            };
+
+            // exit if there was no valid transition
+            if next_state == DEAD {
+                return false;
+            }
+            state = next_state as usize;
+        }
+
+        self.accepting[state]
+    }
+
+    /// Count the states, each of which has a row in the table.
+    pub fn state_count(&self) -> usize {
+        self.table.len()
+    }
+}
+
+/// The parts of a DFA that get serialized.
+/// Transitions are written as a list, since formats like JSON only allow
+/// strings as map keys.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct SerializedDfa {
+    transitions: Vec<(u32, Option<u32>, u32)>,
+    ranges: Vec<(char, char, u32)>,
+    accepting_states: HashSet<u32>,
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Dfa {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedDfa {
+            transitions: self
+                .transitions
+                .iter()
+                .map(|((from, input), to)| (*from, *input, *to))
+                .collect(),
+            ranges: self.ranges.clone(),
+            accepting_states: self.accepting_states.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Dfa {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Dfa, D::Error> {
+        let dfa = SerializedDfa::deserialize(deserializer)?;
+        let transitions = dfa
+            .transitions
+            .into_iter()
+            .map(|(from, input, to)| ((from, input), to))
+            .collect();
+        Ok(Dfa::from_classes(
+            transitions,
+            dfa.ranges,
+            dfa.accepting_states,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_on_empty_string() {
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(0);
+        let dfa = Dfa::new(HashMap::new(), accepting_states);
+
+        assert!(dfa.run(""));
+
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(1);
+        let mut transitions = HashMap::new();
+        transitions.insert((0, Some('0')), 1);
+        let dfa = Dfa::new(transitions, accepting_states);
+
+        assert!(!dfa.run(""));
+    }
+
+    #[test]
+    fn transition_on_some() {
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(1);
+        let mut transitions = HashMap::new();
+        transitions.insert((0, None), 1);
+        transitions.insert((0, Some('0')), 2);
+        let dfa = Dfa::new(transitions, accepting_states);
+
+        assert!(!dfa.run("0"));
+        assert!(dfa.run("1"));
+        // nothing leaves state 1
+        assert!(!dfa.run("11"));
+    }
+
+    #[test]
+    fn to_dot() {
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(1);
+        let mut transitions = HashMap::new();
+        transitions.insert((0, None), 1);
+        transitions.insert((0, Some('"')), 2);
+
+        assert_eq!(
+            Dfa::new(transitions, accepting_states).to_dot(),
+            "digraph {\n    rankdir=LR;\n    0 [shape=circle];\n    1 [shape=doublecircle];\n    2 [shape=circle];\n    0 -> 1 [label=\"else\"];\n    0 -> 2 [label=\"\\\"\"];\n}\n"
+        );
+        // a class is labelled with its ranges
+        assert!(crate::compile("^[a-cx]$")
+            .to_dfa()
+            .to_dot()
+            .contains("[label=\"[a-cx]\"]"));
+    }
+
+    fn state_count(dfa: &Dfa) -> usize {
+        let mut states = HashSet::new();
+        states.insert(0);
+        for ((from, _), to) in &dfa.transitions {
+            states.insert(*from);
+            states.insert(*to);
+        }
+        states.len()
+    }
+
+    #[test]
+    fn minimize_merges_equivalent_states() {
+        // 1 and 2 both only accept a `c`
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(3);
+        let mut transitions = HashMap::new();
+        transitions.insert((0, Some('a')), 1);
+        transitions.insert((0, Some('b')), 2);
+        transitions.insert((1, Some('c')), 3);
+        transitions.insert((2, Some('c')), 3);
+        // 4 can never accept
+        transitions.insert((0, Some('d')), 4);
+        transitions.insert((4, None), 4);
+        let dfa = Dfa::new(transitions, accepting_states).minimize();
+
+        assert_eq!(state_count(&dfa), 3);
+        assert!(dfa.run("ac"));
+        assert!(dfa.run("bc"));
+        assert!(!dfa.run("c"));
+        assert!(!dfa.run("dc"));
+    }
+
+    #[test]
+    fn minimize_keeps_dead_end_that_else_would_miss() {
+        // everything but `a` is accepted
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(1);
+        let mut transitions = HashMap::new();
+        transitions.insert((0, None), 1);
+        transitions.insert((0, Some('a')), 2);
+        let dfa = Dfa::new(transitions, accepting_states).minimize();
+
+        assert!(dfa.run("b"));
+        assert!(!dfa.run("a"));
+        assert!(!dfa.run(""));
+    }
+
+    #[test]
+    fn minimize_empty_language() {
+        let mut transitions = HashMap::new();
+        transitions.insert((0, None), 1);
+        let dfa = Dfa::new(transitions, HashSet::new()).minimize();
+
+        assert_eq!(state_count(&dfa), 1);
+        assert!(!dfa.run(""));
+        assert!(!dfa.run("a"));
+    }
+
+    #[test]
+    fn dense_dfa_accepts_same_as_dfa() {
+        for pattern in &["^(a|b)*abb$", "^[a-cé]+x?$", "ab|\\d", "^.→?$", "^$"] {
+            let dfa = crate::compile(pattern).to_dfa();
+            let dense = dfa.to_dense();
+            for input in &[
+                "abb", "aabb", "ab", "", "abcéx", "é", "3", "x→", "→", "\u{100}",
+            ] {
+                assert_eq!(
+                    dense.run_dense(input),
+                    dfa.run(input),
+                    "{:?} on {:?}",
+                    pattern,
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn dense_dfa_with_gaps_between_states() {
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(7);
+        let mut transitions = HashMap::new();
+        transitions.insert((0, Some('ÿ')), 5);
+        transitions.insert((0, Some('→')), 7);
+        transitions.insert((5, None), 7);
+        transitions.insert((5, Some('a')), 0);
+        let dense = Dfa::new(transitions, accepting_states).to_dense();
+
+        assert_eq!(dense.state_count(), 3);
+        assert!(dense.run_dense("ÿ→"));
+        assert!(dense.run_dense("ÿb"));
+        assert!(dense.run_dense("ÿaÿz"));
+        assert!(dense.run_dense("→"));
+        assert!(!dense.run_dense("ÿa"));
+        assert!(!dense.run_dense("b"));
+        assert!(!dense.run_dense("→a"));
+    }
+
+    #[test]
+    fn stop_in_dead_state() {
+        // reads `a`s, then a `c` to accept; anything else leads to state 2,
+        // which reads anything but can never accept
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(1);
+        let mut transitions = HashMap::new();
+        transitions.insert((0, Some('a')), 0);
+        transitions.insert((0, Some('c')), 1);
+        transitions.insert((0, None), 2);
+        transitions.insert((2, None), 2);
+        let dfa = Dfa::new(transitions, accepting_states);
+
+        let input = "ab".to_string() + &"a".repeat(1_000_000) + "c";
+        assert!(!dfa.run(&input));
+        assert!(!dfa.to_dense().run_dense(&input));
+        assert!(dfa.run("aaac"));
+        assert!(dfa.to_dense().run_dense("aaac"));
+    }
+
+    #[test]
+    fn dead_states() {
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(2);
+        let mut transitions = HashMap::new();
+        transitions.insert((0, Some('a')), 1);
+        transitions.insert((0, None), 3);
+        transitions.insert((1, Some('b')), 2);
+        transitions.insert((3, None), 4);
+        transitions.insert((4, None), 3);
+        let dfa = Dfa::new(transitions, accepting_states);
+
+        let expected: HashSet<u32> = [3, 4].iter().copied().collect();
+        assert_eq!(dfa.dead_states, expected);
+        assert!(dfa.run("ab"));
+        assert!(!dfa.run("xab"));
+    }
+
+    #[test]
+    fn round_trip_through_nfa() {
+        let inputs = ["", "a", "ab", "abb", "aabb", "babb", "abbc", "c", "éabb"];
+        for pattern in &[
+            "^(a|b)*abb$",
+            "ab+",
+            "^a?b?$",
+            "^[ac]b$",
+            "b$",
+            "^[b-y]+a?$",
+        ] {
+            let nfa = crate::compile(pattern);
+            let round_trip = nfa.to_dfa().to_nfa();
+            for input in &inputs {
+                assert_eq!(
+                    round_trip.run(input),
+                    nfa.run(input),
+                    "{:?} on {:?}",
+                    pattern,
+                    input
+                );
+            }
+            assert_eq!(round_trip.to_dfa().minimize().run("abb"), nfa.run("abb"));
+        }
+
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(1);
+        let mut transitions = HashMap::new();
+        transitions.insert((0, Some('a')), 1);
+        transitions.insert((0, None), 0);
+        let nfa = Dfa::new(transitions, accepting_states).to_nfa();
+        assert_eq!(nfa.transition_count(), 2);
+        assert!(nfa.run("xxa"));
+        assert!(!nfa.run("xxax"));
+    }
+
+    #[test]
+    fn categories_are_read_a_class_at_a_time() {
+        let dfa = crate::compile("^\\p{L}+x\\p{N}+$").to_dfa();
+        // letters and numbers outside of ASCII each make one class, rather
+        // than one edge for each character
+        assert!(dfa.class_ranges().len() < 10);
+        for input in &["héllox٣7", "x٣", "héllo7", "x", "٣", "ab\u{0301}x1"] {
+            assert_eq!(
+                dfa.run(input),
+                crate::compile("^\\p{L}+x\\p{N}+$").run(input)
+            );
+            assert_eq!(dfa.to_dense().run_dense(input), dfa.run(input));
+        }
+        assert!(dfa.run("héllox٣7"));
+    }
+
+    #[test]
+    fn cloned_dfa_matches_the_same() {
+        let dfa = crate::compile("ab*c|d").to_dfa();
+        let clone = dfa.clone();
+        for input in &["ac", "abbbc", "d", "", "ab", "dd"] {
+            assert_eq!(clone.run(input), dfa.run(input), "{:?}", input);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// Write a character the way it would be written in an expression,
+/// so control characters stay visible.
+pub fn char_label(c: char) -> String {
+    match c {
+        '\n' => String::from("\\n"),
+        '\t' => String::from("\\t"),
+        '\r' => String::from("\\r"),
+        c if c.is_control() => c.escape_default().collect(),
+        c => c.to_string(),
+    }
+}
+
+/// Write ranges of characters the way a character class would list them,
+/// leaving the `-` out between two consecutive characters.
+pub fn ranges_label(ranges: &[(char, char)]) -> String {
+    let mut label = String::new();
+    for (low, high) in ranges {
+        label.push_str(&char_label(*low));
+        if high != low {
+            if *high as u32 > *low as u32 + 1 {
+                label.push('-');
+            }
+            label.push_str(&char_label(*high));
+        }
+    }
+    label
+}
+
+/// Write a `digraph` with a node for each state and an edge for each
+/// `(from, to, label)`, double circling accepting states.
+///
+/// Nodes and edges are sorted so the same automaton always gives the same output.
+pub fn digraph(
+    mut states: BTreeSet<u32>,
+    accepting_states: &HashSet<u32>,
+    mut edges: Vec<(u32, u32, String)>,
+) -> String {
+    states.insert(0);
+    states.extend(accepting_states);
+    for (from, to, _) in &edges {
+        states.insert(*from);
+        states.insert(*to);
+    }
+    edges.sort();
+
+    let mut dot = String::from("digraph {\n    rankdir=LR;\n");
+    for state in states {
+        let shape = if accepting_states.contains(&state) {
+            "doublecircle"
+        } else {
+            "circle"
+        };
+        writeln!(dot, "    {} [shape={}];", state, shape).unwrap();
+    }
+    for (from, to, label) in edges {
+        let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+        writeln!(dot, "    {} -> {} [label=\"{}\"];", from, to, label).unwrap();
+    }
+    dot.push_str("}\n");
+    dot
+}
@@ -0,0 +1,115 @@
+use std::error::Error;
+use std::fmt;
+
+/// Reasons a regular expression can fail to compile.
+///
+/// Each reason comes with the position of the problem in the expression,
+/// counted in characters from the start.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RegexError {
+    /// The expression ends with a `\` that has nothing to escape.
+    DanglingEscape(usize),
+    /// A quantifier such as `*` has nothing before it to repeat.
+    NothingToRepeat(usize),
+    /// A `[` was opened without a matching `]`.
+    UnbalancedBracket(usize),
+    /// A `(` was opened without a matching `)`, or a `)` was never opened.
+    UnbalancedParenthesis(usize),
+    /// A counted repetition is malformed, as in `a{}` or `a{2,1}`.
+    /// The position is that of its `{`.
+    InvalidRepetition(usize),
+    /// A range inside a character class ends before it starts, as in `[z-a]`.
+    InvalidRange(usize),
+    /// A character class has no members, as in `[]` or `[^]`, so it could
+    /// never match. A `]` straight after the `[` or `[^` is a member if the
+    /// class is closed later, as in `[]]`. The position is that of the `[`.
+    EmptyCharClass(usize),
+    /// A group name is empty, malformed or used twice, as in `(?P<1>a)`.
+    /// The position is that of the group's `(`.
+    InvalidGroupName(usize),
+    /// A group that sets flags, as in `(?i)`, has none or one that isn't
+    /// `i`, `m` or `s`. The position is that of the group's `(`.
+    UnknownFlag(usize),
+    /// A counted repetition expands to more than the repetition limit,
+    /// as in `a{1000000}`. The position is that of its `{`.
+    RepetitionTooLarge(usize),
+    /// A `\p` or `\P` doesn't name a known Unicode category, as in `\p{X}`.
+    /// The position is that of its `\`.
+    UnknownCategory(usize),
+    /// A `\` is followed by a digit, as in the backreference `(a)\1`,
+    /// which can't be matched without remembering what a group matched.
+    /// The position is that of the `\`.
+    UnsupportedBackreference(usize),
+    /// A `\` is followed by a character it gives no meaning to, as in `\q`,
+    /// with `RegexBuilder::strict_escapes` set, a shorthand like `\d` is
+    /// written inside a class, or a `\x` escape isn't a character's hex code.
+    /// The position is that of the `\`.
+    InvalidEscape(usize),
+    /// The expression would compile to more states than the state limit.
+    TooComplex,
+    /// The expression can be shown to match nothing at all, as `a\bb` can't
+    /// since there is no word boundary between two letters.
+    NeverMatches,
+}
+
+impl RegexError {
+    /// The position of the problem in the expression, in characters.
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            RegexError::DanglingEscape(position)
+            | RegexError::NothingToRepeat(position)
+            | RegexError::UnbalancedBracket(position)
+            | RegexError::UnbalancedParenthesis(position)
+            | RegexError::InvalidRepetition(position)
+            | RegexError::InvalidRange(position)
+            | RegexError::EmptyCharClass(position)
+            | RegexError::InvalidGroupName(position)
+            | RegexError::UnknownFlag(position)
+            | RegexError::RepetitionTooLarge(position)
+            | RegexError::UnknownCategory(position)
+            | RegexError::UnsupportedBackreference(position)
+            | RegexError::InvalidEscape(position) => Some(*position),
+            RegexError::TooComplex | RegexError::NeverMatches => None,
+        }
+    }
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            RegexError::DanglingEscape(_) => "trailing `\\` has nothing to escape",
+            RegexError::NothingToRepeat(_) => "quantifier has nothing to repeat",
+            RegexError::UnbalancedBracket(_) => "`[` is never closed",
+            RegexError::UnbalancedParenthesis(_) => "parentheses are unbalanced",
+            RegexError::InvalidRepetition(_) => "counted repetition is malformed",
+            RegexError::InvalidRange(_) => "character range is out of order",
+            RegexError::EmptyCharClass(_) => "character class is empty",
+            RegexError::InvalidGroupName(_) => "group name is invalid or already used",
+            RegexError::UnknownFlag(_) => "inline flag is unknown",
+            RegexError::RepetitionTooLarge(_) => "counted repetition is too large",
+            RegexError::UnknownCategory(_) => "Unicode category is unknown",
+            RegexError::UnsupportedBackreference(_) => "backreferences are not supported",
+            RegexError::InvalidEscape(_) => "escape has no meaning",
+            RegexError::TooComplex => "expression compiles to too many states",
+            RegexError::NeverMatches => "expression can never match",
+        };
+        match self.position() {
+            Some(position) => write!(f, "{} at position {}", message, position),
+            None => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for RegexError {}
+
+/// A match was given up on because it took more steps than its budget.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BudgetExceeded;
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "match took more steps than its budget")
+    }
+}
+
+impl Error for BudgetExceeded {}
@@ -0,0 +1,119 @@
+//! Checks random patterns and inputs against the `regex` crate, with the
+//! `fuzz` feature on.
+//!
+//! Patterns only use syntax that both crates read the same way, so some of
+//! this crate's intentional differences are never generated:
+//!
+//! - `^` and `$` are only anchors at the very start and end of a branch of the
+//!   whole expression, and literal anywhere else, so they are only generated
+//!   there.
+//! - A quantifier on a quantifier, like `a**`, is the same as a single one
+//!   here but an error there, so quantifiers are only put on atoms and groups.
+//! - `\d`, `\w` and `\b` differ outside of ASCII, and `\p{..}` categories are
+//!   only approximated, so inputs are ASCII and categories aren't generated.
+//!
+//! Where a match ends can differ too, when a repeated group can match nothing:
+//! after a pass that read nothing, here another pass can still try a later
+//! alternative, where the `regex` crate stops repeating. So `(?:\d*|\w*)+`
+//! matches `ab` here but nothing there. Only `is_match` is compared.
+
+use crate::Regex;
+use crate::RegexError;
+
+/// A xorshift generator, so that every run checks the same cases.
+struct Random(u64);
+
+impl Random {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+        choices[self.below(choices.len())]
+    }
+}
+
+const ATOMS: &[&str] = &[
+    "a", "b", "c", ".", "[ab]", "[^a]", "[a-c]", "\\d", "\\w", "\\s", "-", "\\.",
+];
+const QUANTIFIERS: &[&str] = &["*", "+", "?", "{2}", "{0,2}", "{1,}"];
+
+/// A sequence of pieces, each an atom or a group, sometimes quantified.
+fn concat(random: &mut Random, depth: usize) -> String {
+    let mut pattern = String::new();
+    for _ in 0..1 + random.below(3) {
+        if depth < 2 && random.below(4) == 0 {
+            let open = random.pick(&["(", "(?:"]);
+            pattern.push_str(open);
+            pattern.push_str(&alternation(random, depth + 1));
+            pattern.push(')');
+        } else if random.below(8) == 0 {
+            pattern.push_str("\\b");
+            continue;
+        } else {
+            pattern.push_str(random.pick(ATOMS));
+        }
+        if random.below(3) == 0 {
+            pattern.push_str(random.pick(QUANTIFIERS));
+        }
+    }
+    pattern
+}
+
+fn alternation(random: &mut Random, depth: usize) -> String {
+    let branches: Vec<String> = (0..1 + random.below(2))
+        .map(|_| concat(random, depth))
+        .collect();
+    branches.join("|")
+}
+
+/// A whole expression, whose branches can be anchored.
+fn pattern(random: &mut Random) -> String {
+    let branches: Vec<String> = (0..1 + random.below(2))
+        .map(|_| {
+            let mut branch = concat(random, 0);
+            if random.below(3) == 0 {
+                branch.insert(0, '^');
+            }
+            if random.below(3) == 0 {
+                branch.push('$');
+            }
+            branch
+        })
+        .collect();
+    branches.join("|")
+}
+
+fn input(random: &mut Random) -> String {
+    (0..random.below(8))
+        .map(|_| random.pick(&["a", "b", "c", "1", " ", "-", ".", "\n"]))
+        .collect()
+}
+
+#[test]
+fn agrees_with_reference() {
+    let mut random = Random(0x2545_f491_4f6c_dd1d);
+    for _ in 0..2000 {
+        let pattern = pattern(&mut random);
+        // a pattern that is rejected for never matching mustn't match there
+        // either
+        let regex = match Regex::new(&pattern) {
+            Err(RegexError::NeverMatches) => None,
+            regex => Some(regex.unwrap()),
+        };
+        let reference = reference::Regex::new(&pattern).unwrap();
+        for _ in 0..20 {
+            let text = input(&mut random);
+            assert_eq!(
+                regex.as_ref().is_some_and(|regex| regex.is_match(&text)),
+                reference.is_match(&text),
+                "{:?} on {:?}",
+                pattern,
+                text
+            );
+        }
+    }
+}
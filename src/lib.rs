@@ -1,160 +1,339 @@
+mod automaton;
+mod category;
+mod dfa;
+mod dot;
+mod error;
+#[cfg(all(test, feature = "fuzz"))]
+mod fuzz;
 mod nfa;
+mod parse;
+mod regex;
+pub use automaton::Automaton;
+pub use dfa::DenseDfa;
+pub use dfa::Dfa;
+pub use error::BudgetExceeded;
+pub use error::RegexError;
+use nfa::add_transition;
+pub use nfa::MatchOutcome;
+pub use nfa::Matcher;
 use nfa::NFAChar;
-use nfa::Nfa;
+pub use nfa::Nfa;
 use nfa::Transition;
+use parse::Expression;
+use parse::Flags;
+use parse::Node;
+pub use regex::CaptureMatches;
+pub use regex::Matches;
+pub use regex::OverlappingMatches;
+pub use regex::Regex;
+pub use regex::RegexBuilder;
 use std::collections::HashSet;
 
-fn check_start(expression: &str) -> (bool, Transition) {
-    let mut transitions = Transition::new();
-    // If first char is ^ then process differently
-    if expression.starts_with('^') {
-        return (true, transitions);
-    } else {
-        // add loop until first char is read
-        transitions.insert((0, NFAChar::Else), 0);
+/// The most characters a counted repetition may expand to by default,
+/// so that a pattern like `a{1000000}` can't make compiling use up memory.
+pub const DEFAULT_REPETITION_LIMIT: usize = 10_000;
+
+/// The most states an expression may compile to by default, so that a
+/// pattern can't make compiling use up memory.
+pub const DEFAULT_STATE_LIMIT: usize = 100_000;
+
+/// Allocate the next unused state.
+fn new_state(next_state: &mut u32) -> u32 {
+    let state = *next_state;
+    *next_state += 1;
+    state
+}
+
+/// Returns the state the expression starts being read from.
+///
+/// The `^` itself is checked by an assertion in the expression, so this only
+/// saves looking for a match anywhere but the start.
+fn check_start(anchored_start: bool, transitions: &mut Transition, next_state: &mut u32) -> u32 {
+    // If every branch starts with ^ then read the expression straight from the start state
+    if anchored_start {
+        return 0;
     }
 
-    (false, transitions)
+    // add loop until first char is read
+    add_transition(transitions, 0, NFAChar::Any, 0);
+    let start = new_state(next_state);
+    add_transition(transitions, 0, NFAChar::Epsilon, start);
+
+    start
 }
 
-fn match_character(
-    c: char,
-    curr_state: &mut u32,
+/// Lets anything be read after the expression, unless every branch ends
+/// with a `$` that its assertion would only let through at the end anyway.
+fn check_end(anchored_end: bool, transitions: &mut Transition, end: u32) {
+    // If every branch ends with $ then nothing may be read after the expression
+    if !anchored_end {
+        // add loop after end of expression is read
+        add_transition(transitions, end, NFAChar::Any, end);
+    }
+}
+
+/// Add a transition on `input`, which reads nothing, from state `from`.
+/// Returns the state it leads to.
+///
+/// The transition is reached through an epsilon transition of its own, so it
+/// keeps its place in order of preference among the other epsilon transitions
+/// out of `from`.
+fn add_zero_width(
+    input: NFAChar,
+    from: u32,
     transitions: &mut Transition,
-    escape: &mut bool,
-    last_char: &mut Option<char>,
-    end: &mut bool,
-) {
-    if !*escape {
-        match c {
-            '.' => {
-                // wildcard
-                transitions.insert((*curr_state - 1, NFAChar::Else), *curr_state);
-            }
-            '\\' => {
-                // escape
-                *escape = true;
-                *curr_state -= 1;
-            }
-            '$' => {
-                // end
-                *end = true;
-                transitions.insert((*curr_state - 1, NFAChar::If(c)), *curr_state);
-            }
-            '?' => {
-                // zero or one
-                *curr_state -= 1;
-                transitions.insert((*curr_state - 1, NFAChar::Epsilon), *curr_state);
+    next_state: &mut u32,
+) -> u32 {
+    let start = new_state(next_state);
+    add_transition(transitions, from, NFAChar::Epsilon, start);
+    let to = new_state(next_state);
+    add_transition(transitions, start, input, to);
+    to
+}
+
+/// Add the transitions for `node`, reading it from state `from`.
+/// Returns the state reached once the whole node has been read.
+fn build(node: &Node, from: u32, transitions: &mut Transition, next_state: &mut u32) -> u32 {
+    match node {
+        Node::Empty => from,
+        Node::Char(c) => {
+            let to = new_state(next_state);
+            add_transition(transitions, from, NFAChar::If(*c), to);
+            to
+        }
+        Node::Class(members) => {
+            let to = new_state(next_state);
+            add_transition(transitions, from, NFAChar::OneOf(members.clone()), to);
+            to
+        }
+        Node::NotClass(members) => {
+            let to = new_state(next_state);
+            add_transition(transitions, from, NFAChar::NoneOf(members.clone()), to);
+            to
+        }
+        Node::Predicate(predicate) => {
+            let to = new_state(next_state);
+            add_transition(transitions, from, NFAChar::Predicate(*predicate), to);
+            to
+        }
+        Node::Wildcard => {
+            let to = new_state(next_state);
+            add_transition(transitions, from, NFAChar::Any, to);
+            to
+        }
+        Node::Assert(kind) => add_zero_width(NFAChar::Assert(*kind), from, transitions, next_state),
+        Node::Concat(nodes) => nodes.iter().fold(from, |state, node| {
+            build(node, state, transitions, next_state)
+        }),
+        Node::Alternation(branches) => {
+            // each branch is read from its own state and they all meet at the end
+            let ends: Vec<u32> = branches
+                .iter()
+                .map(|branch| {
+                    let start = new_state(next_state);
+                    add_transition(transitions, from, NFAChar::Epsilon, start);
+                    build(branch, start, transitions, next_state)
+                })
+                .collect();
+
+            let to = new_state(next_state);
+            for end in ends {
+                add_transition(transitions, end, NFAChar::Epsilon, to);
             }
-            '*' => {
-                // zero or more
-                match last_char {
-                    Some(c) => {
-                        *curr_state -= 1;
-                        transitions.remove(&(*curr_state - 1, NFAChar::If(*c)));
-                        transitions.insert((*curr_state - 1, NFAChar::Epsilon), *curr_state);
-                        transitions.insert((*curr_state, NFAChar::If(*c)), *curr_state);
-                    }
-                    None => {
-                        // normal character
-                        transitions.insert((*curr_state - 1, NFAChar::If(c)), *curr_state);
-                        *last_char = Some(c);
-                    }
-                };
+            to
+        }
+        Node::Group { node, index: None } => build(node, from, transitions, next_state),
+        Node::Group {
+            node,
+            index: Some(index),
+        } => {
+            // note where the group starts and ends
+            let start = add_zero_width(NFAChar::Save(2 * index), from, transitions, next_state);
+            let end = build(node, start, transitions, next_state);
+
+            let to = new_state(next_state);
+            add_transition(transitions, end, NFAChar::Save(2 * index + 1), to);
+            to
+        }
+        Node::ZeroOrOne(node) => {
+            let start = new_state(next_state);
+            add_transition(transitions, from, NFAChar::Epsilon, start);
+            let end = build(node, start, transitions, next_state);
+
+            let to = new_state(next_state);
+            add_transition(transitions, end, NFAChar::Epsilon, to);
+            // skip the node
+            add_transition(transitions, start, NFAChar::Epsilon, to);
+            to
+        }
+        Node::ZeroOrMore(node) => build_zero_or_more(node, from, transitions, next_state),
+        Node::OneOrMore(node) => {
+            let start = new_state(next_state);
+            add_transition(transitions, from, NFAChar::Epsilon, start);
+            let end = build(node, start, transitions, next_state);
+            // go back for another
+            add_transition(transitions, end, NFAChar::Epsilon, start);
+
+            let to = new_state(next_state);
+            add_transition(transitions, end, NFAChar::Epsilon, to);
+            to
+        }
+        Node::Repeat { node, min, max } => {
+            // read the node as many times as it is required
+            let mut state = from;
+            for _ in 0..*min {
+                state = build(node, state, transitions, next_state);
             }
-            '+' => {
-                // one or more
-                match last_char {
-                    Some(c) => {
-                        *curr_state -= 1;
-                        transitions.insert((*curr_state, NFAChar::If(*c)), *curr_state);
-                    }
-                    None => {
-                        // normal character
-                        transitions.insert((*curr_state - 1, NFAChar::If(c)), *curr_state);
-                        *last_char = Some(c);
+
+            match max {
+                None => build_zero_or_more(node, state, transitions, next_state),
+                Some(max) => {
+                    // then read it up to the maximum, with a way out before each one
+                    let to = new_state(next_state);
+                    for _ in *min..*max {
+                        let start = state;
+                        state = build(node, start, transitions, next_state);
+                        add_transition(transitions, start, NFAChar::Epsilon, to);
                     }
-                };
-            }
-            _ => {
-                // normal character
-                transitions.insert((*curr_state - 1, NFAChar::If(c)), *curr_state);
-                *last_char = Some(c);
+                    add_transition(transitions, state, NFAChar::Epsilon, to);
+                    to
+                }
             }
         }
-    } else {
-        // insert escaped character
-        transitions.insert((*curr_state - 1, NFAChar::If(c)), *curr_state);
-        *last_char = Some(c);
-        *escape = false;
     }
 }
 
-fn check_end(transitions: &mut Transition, curr_state: &mut u32, end: &bool) {
-    // If last char is $ then remove last transition
-    if *end {
-        *curr_state -= 1;
-        transitions.remove(&(*curr_state, NFAChar::If('$')));
-    } else {
-        // add loop after end of expression is read
-        transitions.insert((*curr_state, NFAChar::Else), *curr_state);
+/// Count the states `build` adds for `node`, without building them.
+fn state_count(node: &Node) -> usize {
+    match node {
+        Node::Empty => 0,
+        Node::Char(_)
+        | Node::Class(_)
+        | Node::NotClass(_)
+        | Node::Predicate(_)
+        | Node::Wildcard => 1,
+        Node::Assert(_) => 2,
+        Node::Concat(nodes) => nodes
+            .iter()
+            .fold(0, |count, node| count.saturating_add(state_count(node))),
+        Node::Alternation(branches) => branches.iter().fold(1, |count, branch| {
+            count.saturating_add(state_count(branch).saturating_add(1))
+        }),
+        Node::Group { node, index: None } => state_count(node),
+        Node::Group { node, .. } => state_count(node).saturating_add(3),
+        Node::ZeroOrOne(node) | Node::ZeroOrMore(node) | Node::OneOrMore(node) => {
+            state_count(node).saturating_add(2)
+        }
+        Node::Repeat { node, min, max } => {
+            let count = state_count(node);
+            let optional = match max {
+                None => count.saturating_add(2),
+                Some(max) => count.saturating_mul((max - min) as usize).saturating_add(1),
+            };
+            count.saturating_mul(*min as usize).saturating_add(optional)
+        }
     }
 }
 
-fn iterate_through_expression(
-    expression: String,
+/// Fails with `TooComplex` if `expression`, along with `anchored` more copies
+/// of it anchored at the start, would compile to more than `state_limit`
+/// states.
+fn check_state_count(
+    expression: &Expression,
+    anchored: usize,
+    state_limit: usize,
+) -> Result<(), RegexError> {
+    let count = state_count(&expression.node);
+    // the start state, and the state the expression is read from if it is
+    // not anchored
+    let extra = if expression.anchored_start { 1 } else { 2 };
+    let copies = count.saturating_add(1).saturating_mul(anchored);
+    if count.saturating_add(extra).saturating_add(copies) > state_limit {
+        return Err(RegexError::TooComplex);
+    }
+    Ok(())
+}
+
+fn build_zero_or_more(
+    node: &Node,
+    from: u32,
     transitions: &mut Transition,
-    start: &mut bool,
-    end: &mut bool,
+    next_state: &mut u32,
 ) -> u32 {
-    let mut current_state = 0;
-    let mut escape = false;
-    let mut last_char = None;
-    for c in expression.chars() {
-        current_state += 1;
-        *end = false;
-
-        // If ^ was at the start then don't read it
-        if *start {
-            current_state -= 1;
-            *start = false;
-            continue;
-        }
+    let start = new_state(next_state);
+    add_transition(transitions, from, NFAChar::Epsilon, start);
+    let end = build(node, start, transitions, next_state);
+    // go back for another
+    add_transition(transitions, end, NFAChar::Epsilon, start);
 
-        match_character(
-            c,
-            &mut current_state,
-            transitions,
-            &mut escape,
-            &mut last_char,
-            end,
-        );
-    }
-
-    current_state
+    let to = new_state(next_state);
+    // skip the node
+    add_transition(transitions, start, NFAChar::Epsilon, to);
+    to
 }
 
 /// Compiles regular expression into NFA.
 /// Can be reused for multiple runs.
-pub fn compile(expression: String) -> Nfa {
-    let (mut start, mut transitions) = check_start(&expression);
-    let mut end = false;
+///
+/// # Panics
+/// Panics if the expression is malformed; use `try_compile` to handle the error instead.
+pub fn compile(expression: &str) -> Nfa {
+    try_compile(expression).unwrap_or_else(|error| panic!("invalid regular expression: {}", error))
+}
+
+/// Compiles regular expression into NFA, returning an error if it is malformed.
+pub fn try_compile(expression: &str) -> Result<Nfa, RegexError> {
+    try_compile_with_limit(expression, DEFAULT_REPETITION_LIMIT)
+}
+
+/// Compiles regular expression into NFA like `try_compile`, allowing counted
+/// repetitions to expand to at most `repetition_limit` characters rather than
+/// `DEFAULT_REPETITION_LIMIT`.
+pub fn try_compile_with_limit(
+    expression: &str,
+    repetition_limit: usize,
+) -> Result<Nfa, RegexError> {
+    let expression = parse::parse(expression, repetition_limit, false, Flags::default())?;
+    check_state_count(&expression, 0, DEFAULT_STATE_LIMIT)?;
+    let nfa = compile_expression(&expression);
+    if !nfa.can_match() {
+        return Err(RegexError::NeverMatches);
+    }
+    Ok(nfa)
+}
 
-    let mut current_state =
-        iterate_through_expression(expression, &mut transitions, &mut start, &mut end);
+/// Compiles a parsed regular expression into NFA.
+fn compile_expression(expression: &Expression) -> Nfa {
+    let mut transitions = Transition::new();
+    let mut next_state = 1;
 
-    check_end(&mut transitions, &mut current_state, &end);
+    let start = check_start(expression.anchored_start, &mut transitions, &mut next_state);
+    let end = build(&expression.node, start, &mut transitions, &mut next_state);
+    check_end(expression.anchored_end, &mut transitions, end);
 
     // add accepting state
     let mut accepting_states = HashSet::new();
-    accepting_states.insert(current_state);
+    accepting_states.insert(end);
 
     Nfa::new(transitions, accepting_states)
 }
 
-pub fn compile_and_run(expression: String, input: String) -> bool {
-    let dfa = compile(expression);
-    dfa.run(input)
+/// Compiles regular expression into DFA, returning an error if it is malformed.
+///
+/// Converting to a DFA takes longer than compiling to an NFA, but the DFA only
+/// ever has to be in one state, so running it is faster.
+pub fn compile_to_dfa(expression: &str) -> Result<Dfa, RegexError> {
+    Ok(try_compile(expression)?.to_dfa())
+}
+
+/// Compiles regular expression and runs it on a single input.
+///
+/// # Panics
+/// Panics if the expression is malformed.
+pub fn compile_and_run(expression: &str, input: &str) -> bool {
+    let regex = Regex::new(expression)
+        .unwrap_or_else(|error| panic!("invalid regular expression: {}", error));
+    regex.is_match(input)
 }
 
 #[cfg(test)]
@@ -163,70 +342,975 @@ mod tests {
 
     #[test]
     fn run_empty_regex() {
-        assert!(compile_and_run("".to_string(), "".to_string()));
-        assert!(compile_and_run("".to_string(), "afklgjlakj".to_string()))
+        assert!(compile_and_run("", ""));
+        assert!(compile_and_run("", "afklgjlakj"))
     }
 
     #[test]
     fn match_string() {
-        assert!(!compile_and_run("abcd".to_string(), "".to_string()));
-        assert!(compile_and_run("abcd".to_string(), "abcd".to_string()))
+        assert!(!compile_and_run("abcd", ""));
+        assert!(compile_and_run("abcd", "abcd"))
     }
 
     #[test]
     fn match_string_not_at_start() {
-        assert!(compile_and_run("abcd".to_string(), "xxxabcd".to_string()));
+        assert!(compile_and_run("abcd", "xxxabcd"));
+    }
+
+    #[test]
+    fn match_restarts_on_first_character() {
+        // the first `a` starts a match that fails, but the second can still start one
+        assert!(compile_and_run("ab", "aab"));
+        assert!(compile_and_run("aab", "aaab"));
+        assert!(compile_and_run("abac", "ababac"));
+        assert_eq!(compile("ab").find("aab"), Some((1, 3)));
+        assert_eq!(compile("abac").find("ababac"), Some((2, 6)));
     }
 
     #[test]
     fn match_wildcard() {
-        assert!(compile_and_run("a.cd".to_string(), "abcd".to_string()));
-        assert!(compile_and_run("a.cd".to_string(), "axcd".to_string()));
+        assert!(compile_and_run("a.cd", "abcd"));
+        assert!(compile_and_run("a.cd", "axcd"));
     }
 
     #[test]
     fn escape() {
-        assert!(compile_and_run("a\\.cd".to_string(), "a.cd".to_string()));
-        assert!(compile_and_run("a\\\\".to_string(), "a\\".to_string()));
-        assert!(!compile_and_run("a\\.cd".to_string(), "axcd".to_string()));
+        assert!(compile_and_run("a\\.cd", "a.cd"));
+        assert!(compile_and_run("a\\\\", "a\\"));
+        assert!(!compile_and_run("a\\.cd", "axcd"));
+    }
+
+    #[test]
+    fn escape_metacharacters() {
+        assert!(compile_and_run("a\\|b", "a|b"));
+        assert!(!compile_and_run("^a\\|b$", "a"));
+        assert!(compile_and_run("\\(x\\)", "(x)"));
+        assert!(!compile_and_run("\\(x\\)", "x"));
+        for c in parse::METACHARACTERS {
+            let pattern = format!("^\\{}$", c);
+            assert!(
+                compile_and_run(&pattern, &c.to_string()),
+                "pattern {:?}",
+                pattern
+            );
+            // and inside classes
+            let pattern = format!("^[\\{}]$", c);
+            assert!(
+                compile_and_run(&pattern, &c.to_string()),
+                "pattern {:?}",
+                pattern
+            );
+        }
+        assert!(!compile_and_run("^[\\]]$", "\\"));
+        assert!(compile_and_run("^[\\[]$", "["));
+        assert!(compile_and_run("^[\\\\]$", "\\"));
+        assert!(!compile_and_run("^[\\\\]$", "\\\\"));
+        assert!(compile_and_run("^[a\\-z]+$", "-az"));
+        assert!(!compile_and_run("[a\\-z]", "m"));
+        assert!(compile_and_run("^[\\[-\\]]$", "\\"));
+    }
+
+    #[test]
+    fn escaped_backslash_before_shorthand() {
+        // `\\d` is an escaped backslash followed by a literal `d`
+        assert!(compile_and_run("^\\\\d$", "\\d"));
+        assert!(!compile_and_run("\\\\d", "1"));
+        // while `\d` is a digit
+        assert!(compile_and_run("^\\d$", "1"));
+        assert!(!compile_and_run("\\d", "\\d"));
+        // and `\\\d` is a backslash followed by a digit
+        assert!(compile_and_run("^\\\\\\d$", "\\1"));
+        assert!(compile_and_run("^\\\\n$", "\\n"));
+    }
+
+    #[test]
+    fn control_characters() {
+        assert!(compile_and_run("a\\nb", "a\nb"));
+        assert!(!compile_and_run("a\\nb", "anb"));
+        assert!(compile_and_run("\\t", "\t"));
+        assert!(compile_and_run("^\\r\\n$", "\r\n"));
+
+        // inside classes too
+        assert!(compile_and_run("^[\\n]$", "\n"));
+        assert!(!compile_and_run("^[\\n]$", "\\"));
+        assert!(!compile_and_run("^[\\n]$", "n"));
+        assert!(compile_and_run("^[\\t ]+$", "\t \t"));
+        assert!(compile_and_run("^[^\\r\\n]+$", "ab"));
+        assert!(!compile_and_run("[^\\r\\n]", "\r\n"));
+        assert!(compile_and_run("^[\\t-\\r]$", "\u{b}"));
+    }
+
+    #[test]
+    fn hex_escapes() {
+        assert!(compile_and_run("^\\x41$", "A"));
+        assert!(compile_and_run("^\\x411$", "A1"));
+        assert!(compile_and_run("^\\x{1F600}$", "\u{1F600}"));
+        assert!(compile_and_run("^[\\x41-\\x43]+$", "ABC"));
+        assert!(!compile_and_run("[\\x41-\\x43]", "D"));
+        for (expression, position) in &[
+            ("\\x4", 0),
+            ("a\\xzz", 1),
+            ("\\x{}", 0),
+            ("\\x{41", 0),
+            ("\\x{110000}", 0),
+            ("\\x{D800}", 0),
+            ("[\\x{FFFFFFFFF}]", 1),
+        ] {
+            assert_eq!(
+                try_compile(expression).err(),
+                Some(RegexError::InvalidEscape(*position)),
+                "{}",
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn digit() {
+        assert!(compile_and_run("\\d\\d", "42"));
+        assert!(!compile_and_run("\\d", "a"));
+        assert!(compile_and_run("^\\d+$", "2024"));
+        assert!(compile_and_run("\\D", "a"));
+        assert!(!compile_and_run("\\D", "7"));
+
+        // a class can't hold a shorthand, rather than reading it as letters
+        for (expression, position) in &[("[\\d\\s]", 1), ("a[x\\D]", 3), ("[\\p{L}]", 1)] {
+            assert_eq!(
+                try_compile(expression).err(),
+                Some(RegexError::InvalidEscape(*position)),
+                "{}",
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn word() {
+        assert!(compile_and_run("^\\w+$", "hello_123"));
+        assert!(!compile_and_run("\\w", "-"));
+        assert!(compile_and_run("^\\W$", "-"));
+        assert!(!compile_and_run("\\W", "a"));
+    }
+
+    #[test]
+    fn whitespace() {
+        assert!(!compile_and_run("\\s", "x"));
+        assert!(compile_and_run("^a\\s+b$", "a \t\n b"));
+        assert!(compile_and_run("^\\S+$", "abc"));
+        assert!(!compile_and_run("\\S", " "));
+    }
+
+    #[test]
+    fn unicode_category() {
+        assert!(compile_and_run("\\p{L}", "é"));
+        assert!(!compile_and_run("\\p{L}", "7"));
+        assert!(compile_and_run("^\\p{L}+$", "Straße"));
+        assert!(compile_and_run("^\\pN+$", "7٣Ⅻ"));
+        assert!(compile_and_run("^\\P{L}$", "7"));
+        assert!(!compile_and_run("\\P{L}", "é"));
+        assert!(compile_and_run("^\\p{P}\\p{S}\\p{Z}$", "!€ "));
+        assert!(compile_and_run("^e\\p{M}$", "e\u{0301}"));
+        assert!(compile_to_dfa("^\\p{N}+$").unwrap().run("٣7"));
+        assert!(!compile_to_dfa("^\\p{N}+$").unwrap().run("a"));
+
+        for (expression, position) in &[("\\p{X}", 0), ("a\\p{L", 1), ("a\\p", 1), ("\\P{}", 0)] {
+            assert_eq!(
+                try_compile(expression).err(),
+                Some(RegexError::UnknownCategory(*position))
+            );
+        }
     }
 
     #[test]
     fn check_for_start() {
-        assert!(compile_and_run("^abcd".to_string(), "abcd".to_string()));
-        assert!(!compile_and_run("^abcd".to_string(), "xxxabcd".to_string()));
+        assert!(compile_and_run("^abcd", "abcd"));
+        assert!(!compile_and_run("^abcd", "xxxabcd"));
     }
 
     #[test]
     fn check_for_end() {
-        assert!(compile_and_run("abcd$".to_string(), "abcd".to_string()));
-        assert!(!compile_and_run("abcd$".to_string(), "abcdxxx".to_string()));
+        assert!(compile_and_run("abcd$", "abcd"));
+        assert!(!compile_and_run("abcd$", "abcdxxx"));
+        assert!(compile_and_run("$", ""));
+        assert!(compile_and_run("$", "abc"));
+    }
+
+    #[test]
+    fn literal_anchors_away_from_edges() {
+        assert!(compile_and_run("a$b", "a$b"));
+        assert!(!compile_and_run("a$b", "ab"));
+        assert!(compile_and_run("a$b$", "xa$b"));
+        assert!(!compile_and_run("a$b$", "a$bx"));
+        assert!(!compile_and_run("a$b$", "ab"));
+        assert!(compile_and_run("a^b", "xa^b"));
+        assert!(!compile_and_run("a^b", "ab"));
+        // only the outermost ones are anchors
+        assert!(compile_and_run("^^a", "^a"));
+        assert!(!compile_and_run("^^a", "x^a"));
+        assert!(compile_and_run("a$$", "xa$"));
+        assert!(!compile_and_run("a$$", "a$x"));
+        assert_eq!(compile("\\$\\d+$").find("costs $12"), Some((6, 9)));
+        assert!(Regex::new_multiline("^a$b$").unwrap().is_match("x\na$b\ny"));
+    }
+
+    #[test]
+    fn anchors_in_alternatives() {
+        // alternation binds loosest, so this is `(^a)|(b$)`
+        assert!(compile_and_run("^a|b$", "axxx"));
+        assert!(compile_and_run("^a|b$", "xxxb"));
+        assert!(!compile_and_run("^a|b$", "xax"));
+        assert!(!compile_and_run("^a|b$", "bx"));
+        assert!(!compile_and_run("^a|b$", "xa"));
+        assert_eq!(compile("^a|b$").find("bab"), Some((2, 3)));
+        assert!(compile_to_dfa("^a|b$").unwrap().run("axxx"));
+        assert!(compile_to_dfa("^a|b$").unwrap().run("xxxb"));
+        assert!(!compile_to_dfa("^a|b$").unwrap().run("xax"));
+
+        // anchors shared by every branch anchor the whole expression
+        assert!(compile_and_run("^a|^b", "bx"));
+        assert!(!compile_and_run("^a|^b", "xb"));
+        assert!(compile_and_run("^a$|^b$", "b"));
+        assert!(!compile_and_run("^a$|^b$", "ab"));
+        assert!(compile_and_run("x|^y$", "axb"));
+        assert!(!compile_and_run("x|^y$", "ay"));
+        assert!(compile_and_run("x|^y$", "y"));
+        // inside a group they are still literal
+        assert!(compile_and_run("(^a|b)", "x^a"));
+        assert!(compile_and_run("a\\$|b$", "a$x"));
+
+        // `^` and `$` are assertions like `\b`, so they hang together with
+        // what they anchor however it is quantified
+        assert!(compile_and_run("^a*$", ""));
+        assert!(compile_and_run("^a*$", "aaa"));
+        assert!(!compile_and_run("^a*$", "aab"));
+        assert!(compile_and_run("^(ab|c)+$", "abcab"));
+        assert!(!compile_and_run("^(ab|c)+$", "abca"));
+        assert!(compile_and_run("^x?$|^y+", "yyz"));
+        assert!(!compile_and_run("^x?$|^y+", "xx"));
+        assert_eq!(compile("a+$|^b*").find("bbaa"), Some((0, 2)));
+        assert_eq!(compile("x|a+$").find("baa"), Some((1, 3)));
+        assert_eq!(compile("(\\ba)+").find("aa a"), Some((0, 1)));
+
+        let regex = Regex::new_multiline("^a|b$").unwrap();
+        assert!(regex.is_match("x\nab"));
+        assert!(regex.is_match("xb\ny"));
+        assert!(!regex.is_match("xa\nby"));
     }
 
     #[test]
     fn zero_or_one() {
-        assert!(compile_and_run("a?b?c?d".to_string(), "acd".to_string()));
-        assert!(compile_and_run("a?b?c?d".to_string(), "ad".to_string()));
-        assert!(compile_and_run("a?b?c?d".to_string(), "d".to_string()));
+        assert!(compile_and_run("a?b?c?d", "acd"));
+        assert!(compile_and_run("a?b?c?d", "ad"));
+        assert!(compile_and_run("a?b?c?d", "d"));
+    }
+
+    #[test]
+    fn zero_or_one_of_any_token() {
+        assert!(compile_and_run("a\\d?b", "ab"));
+        assert!(compile_and_run("a\\d?b", "a5b"));
+        assert!(!compile_and_run("a\\d?b", "a55b"));
+        assert!(compile_and_run("^x(ab)?y$", "xy"));
+        assert!(compile_and_run("^x(ab)?y$", "xaby"));
+        assert!(!compile_and_run("^x(ab)?y$", "xay"));
+        assert!(compile_and_run("^x[0-9]?\\w?y$", "x1zy"));
+        assert!(compile_and_run("^x[0-9]?\\w?y$", "xzy"));
+        assert!(compile_and_run("^x(a|bc)?.?y$", "xbc-y"));
+        assert!(!compile_and_run("^x(a|bc)?.?y$", "xb-y"));
+        assert!(compile_and_run("^(?:ab){2}?c$", "c"));
+        assert!(compile_and_run("^(?:ab){2}?c$", "ababc"));
+        assert!(!compile_and_run("^(?:ab){2}?c$", "abc"));
     }
 
     #[test]
     fn zero_or_more() {
-        assert!(compile_and_run("a*b*c*d".to_string(), "abcd".to_string()));
-        assert!(compile_and_run("a*b*c*d".to_string(), "aacccd".to_string()));
-        assert!(compile_and_run("a*b*c*d".to_string(), "d".to_string()));
+        assert!(compile_and_run("a*b*c*d", "abcd"));
+        assert!(compile_and_run("a*b*c*d", "aacccd"));
+        assert!(compile_and_run("a*b*c*d", "d"));
+    }
+
+    #[test]
+    fn operators_sharing_a_character() {
+        // a loop and what follows it both read `a`, which used to overwrite
+        // one transition with the other
+        let mut transitions = Transition::new();
+        add_transition(&mut transitions, 0, NFAChar::If('a'), 0);
+        add_transition(&mut transitions, 0, NFAChar::If('a'), 1);
+        assert_eq!(transitions[&(0, NFAChar::If('a'))], [0, 1]);
+
+        assert!(compile_and_run("^a*a$", "aaa"));
+        assert!(!compile_and_run("^a*a$", ""));
+        assert!(compile_and_run("^a?a$", "a"));
+        assert!(compile_and_run("^(a|ab)*ab$", "aabab"));
+        assert!(compile_and_run("^(?:a*)*a+$", "a"));
+        assert_eq!(compile("a*ab").find("xaaab"), Some((1, 5)));
+    }
+
+    #[test]
+    fn quantified_wildcard() {
+        assert!(compile_and_run("a.*b", "axyzb"));
+        assert!(compile_and_run("^a.*b$", "ab"));
+        assert!(compile_and_run("a.+b", "axb"));
+        assert!(!compile_and_run("^a.+b$", "ab"));
+        assert_eq!(compile("a.+b").find("ab axxb"), Some((0, 7)));
+    }
+
+    #[test]
+    fn leading_wildcard() {
+        // the loop that looks for a match and the first `.` each keep
+        // their own transition out of the start
+        assert!(compile_and_run(".bc", "xbc"));
+        assert!(compile_and_run(".bc", "aaxbc"));
+        assert!(!compile_and_run(".bc", "bc"));
+        assert!(!compile_and_run(".bc", "x\nbc"));
+        assert_eq!(compile(".bc").find("abxbc"), Some((2, 5)));
+        assert_eq!(compile("..").find("abc"), Some((0, 2)));
+        assert!(compile_to_dfa(".bc").unwrap().run("xxbc"));
+        assert!(!compile_to_dfa(".bc").unwrap().run("bcx"));
+        assert!(Regex::new_dotall(".bc").unwrap().is_match("\nbc"));
+    }
+
+    #[test]
+    fn zero_or_more_of_any_token() {
+        assert!(compile_and_run("^.*c$", "xyzc"));
+        assert!(compile_and_run("^\\.*c$", "..c"));
+        assert!(!compile_and_run("^\\.*c$", "xc"));
+        assert!(compile_and_run("^\\d*c$", "123c"));
+        assert!(!compile_and_run("^\\d*c$", "1a3c"));
+        assert!(compile_and_run("^[ab]*c$", "abbac"));
+        assert!(compile_and_run("^x\\w*$", "x"));
     }
 
     #[test]
     fn one_or_more() {
-        assert!(compile_and_run("a+b+c+d".to_string(), "abcd".to_string()));
-        assert!(compile_and_run(
-            "a+b+c+d".to_string(),
-            "aaabbccccd".to_string()
-        ));
-        assert!(!compile_and_run(
-            "a+b+c+d".to_string(),
-            "aaccccd".to_string()
-        ));
+        assert!(compile_and_run("a+b+c+d", "abcd"));
+        assert!(compile_and_run("a+b+c+d", "aaabbccccd"));
+        assert!(!compile_and_run("a+b+c+d", "aaccccd"));
+    }
+
+    #[test]
+    fn several_paths_at_once() {
+        assert!(compile_and_run("a.*b.*c", "aabxbxc"));
+        assert!(!compile_and_run("a.*b.*c", "acb"));
+        assert!(compile_and_run("^(ab|a)c$", "ac"));
+    }
+
+    #[test]
+    fn character_class() {
+        assert!(compile_and_run("[abc]d", "bd"));
+        assert!(compile_and_run("[abc]d", "ad"));
+        assert!(!compile_and_run("[abc]d", "xd"));
+    }
+
+    #[test]
+    fn negated_character_class() {
+        assert!(compile_and_run("^[^abc]d$", "xd"));
+        assert!(!compile_and_run("^[^abc]d$", "bd"));
+        assert!(compile_and_run("^[^a-c\n]$", "é"));
+        assert!(!compile_and_run("^[^a-c\n]$", "\n"));
+        // `^` anywhere else in a class is a member
+        assert!(compile_and_run("^[a^]$", "^"));
+        assert_eq!(compile("[^ ]+").find("  ab c"), Some((2, 4)));
+        assert!(compile_to_dfa("^[^abc]d$").unwrap().run("xd"));
+        assert!(!compile_to_dfa("^[^abc]d$").unwrap().run("ad"));
+        assert!(Regex::new_case_insensitive("^[^a]$").unwrap().is_match("b"));
+        assert!(!Regex::new_case_insensitive("^[^a]$").unwrap().is_match("A"));
+    }
+
+    #[test]
+    fn closing_bracket_first_in_class() {
+        assert!(compile_and_run("^[]]$", "]"));
+        assert!(!compile_and_run("^[]]$", "a"));
+        assert!(compile_and_run("^[]a]+$", "a]a"));
+        assert!(compile_and_run("^[^]]$", "a"));
+        assert!(!compile_and_run("^[^]]$", "]"));
+        assert!(compile_and_run("^[]-a]$", "^"));
+    }
+
+    #[test]
+    fn empty_character_class() {
+        assert_eq!(try_compile("[]").err(), Some(RegexError::EmptyCharClass(0)));
+        assert_eq!(
+            try_compile("a[^]").err(),
+            Some(RegexError::EmptyCharClass(1))
+        );
+        assert_eq!(
+            try_compile("[]a").err(),
+            Some(RegexError::EmptyCharClass(0))
+        );
+        assert_eq!(
+            try_compile("[]a").unwrap_err().to_string(),
+            "character class is empty at position 0"
+        );
+    }
+
+    #[test]
+    fn unterminated_character_class() {
+        assert_eq!(
+            try_compile("[abc").err(),
+            Some(RegexError::UnbalancedBracket(0))
+        );
+        assert_eq!(
+            try_compile("x[a]y[abc").err(),
+            Some(RegexError::UnbalancedBracket(5))
+        );
+    }
+
+    #[test]
+    fn character_range() {
+        assert!(compile_and_run("[a-f]x", "cx"));
+        assert!(!compile_and_run("[a-f]x", "gx"));
+        assert!(compile_and_run("[a-zA-Z0-9_]", "Q"));
+        assert!(compile_and_run("[a-zA-Z0-9_]", "_"));
+        assert!(!compile_and_run("[a-zA-Z0-9_]", "-"));
+    }
+
+    #[test]
+    fn literal_dash_in_class() {
+        assert!(compile_and_run("[-a]", "-"));
+        assert!(compile_and_run("[a-]", "-"));
+        assert!(!compile_and_run("[a-]", "b"));
+    }
+
+    #[test]
+    fn reversed_range() {
+        assert_eq!(
+            try_compile("[z-a]").err(),
+            Some(RegexError::InvalidRange(1))
+        );
+        assert_eq!(
+            try_compile("[a-cz-a]").err(),
+            Some(RegexError::InvalidRange(4))
+        );
+    }
+
+    #[test]
+    fn alternation() {
+        assert!(compile_and_run("cat|dog", "dog"));
+        assert!(compile_and_run("cat|dog", "cat"));
+        assert!(!compile_and_run("cat|dog", "cog"));
+        assert!(compile_and_run("a|b|c", "c"));
+    }
+
+    #[test]
+    fn empty_alternative() {
+        // an empty alternative matches the empty string
+        assert!(compile_and_run("a|", ""));
+        assert!(compile_and_run("^a|$", ""));
+        assert!(compile_and_run("^a|$", "a"));
+        // the `$` anchors the empty alternative on its own, which matches
+        // at the end of anything
+        assert!(compile_and_run("^a|$", "b"));
+        assert!(compile_and_run("(a|)b", "b"));
+        assert!(compile_and_run("^(a|)b$", "ab"));
+        assert!(!compile_and_run("^(a|)b$", "aab"));
+        assert!(compile_and_run("^(|b)c$", "c"));
+        assert!(compile_and_run("^(|b)c$", "bc"));
+        assert!(compile_and_run("^|$", ""));
+        assert!(compile_and_run("|", "x"));
+        // alternatives are preferred in order, even when empty
+        assert_eq!(compile("(a|)b").find("xab"), Some((1, 3)));
+        assert_eq!(compile("(|a)").find("a"), Some((0, 0)));
+        assert_eq!(compile("a|").find("ba"), Some((0, 0)));
+    }
+
+    #[test]
+    fn shared_prefixes() {
+        let keywords = ["foo", "for", "bar", "baz", "break", "if", "in", "fork"];
+        let pattern = keywords.join("|");
+        let nfa = compile(&pattern);
+        // non-capturing groups stop the branches being merged
+        let naive: Vec<String> = keywords.iter().map(|k| format!("(?:{})", k)).collect();
+        assert!(nfa.state_count() < compile(&naive.join("|")).state_count());
+        let whole = compile(&format!("^(?:{})$", pattern));
+        for keyword in &keywords {
+            assert!(whole.run(keyword), "{}", keyword);
+        }
+        assert!(!whole.run("fo"));
+        assert!(!whole.run("bark"));
+        // `for` is preferred over `fork` since it comes first
+        assert_eq!(nfa.find("fork"), Some((0, 3)));
+
+        // branches are still preferred in the order they are written
+        assert_eq!(compile("ab|abc").find("abc"), Some((0, 2)));
+        assert_eq!(compile("abc|ab").find("abc"), Some((0, 3)));
+        assert_eq!(compile("ab|.c|abc").find("abc"), Some((0, 2)));
+        assert_eq!(compile("a|[ab]bc|abc").find("abc"), Some((0, 1)));
+        assert_eq!(
+            compile("x|(a)bc|abd").captures("abd"),
+            Some(vec![Some((0, 3)), None])
+        );
+    }
+
+    #[test]
+    fn group() {
+        assert!(compile_and_run("(ab)+c", "ababc"));
+        assert!(!compile_and_run("(ab)+c", "abac"));
+        assert!(compile_and_run("a(b|c)d", "acd"));
+        assert!(!compile_and_run("a(b|c)d", "ad"));
+    }
+
+    #[test]
+    fn quantified_group() {
+        assert!(compile_and_run("(abc)*d", "abcabcd"));
+        assert!(compile_and_run("^(abc)*d$", "d"));
+        assert!(!compile_and_run("^(abc)*d$", "abcabd"));
+        assert!(compile_and_run("(xy)?z", "z"));
+        assert!(compile_and_run("^(xy)?z$", "xyz"));
+        assert!(!compile_and_run("^(xy)?z$", "xz"));
+    }
+
+    #[test]
+    fn repeat_optional_group() {
+        // the group can match nothing, so repeating it makes an epsilon cycle
+        assert!(compile_and_run("^(a?)*b$", "aab"));
+        assert!(compile_and_run("^(a|)+b$", "b"));
+        assert!(!compile_and_run("^(a?)*b$", "aac"));
+    }
+
+    #[test]
+    fn bounded_repetition() {
+        assert!(compile_and_run("a{3}", "aaa"));
+        assert!(!compile_and_run("a{3}", "aa"));
+        assert!(!compile_and_run("^a{3}$", "aaaa"));
+
+        assert!(!compile_and_run("^a{2,4}$", "a"));
+        assert!(compile_and_run("^a{2,4}$", "aa"));
+        assert!(compile_and_run("^a{2,4}$", "aaa"));
+        assert!(compile_and_run("^a{2,4}$", "aaaa"));
+        assert!(!compile_and_run("^a{2,4}$", "aaaaa"));
+
+        assert!(!compile_and_run("^a{2,}$", "a"));
+        assert!(compile_and_run("^a{2,}$", "aaaaaa"));
+
+        assert!(compile_and_run("^(ab){2}$", "abab"));
+    }
+
+    #[test]
+    fn malformed_repetition() {
+        for expression in &["a{2,1}", "a{}", "a{,2}", "a{2", "a{x}"] {
+            assert_eq!(
+                try_compile(expression).err(),
+                Some(RegexError::InvalidRepetition(1))
+            );
+        }
+        assert_eq!(
+            try_compile("^ab{2,").err(),
+            Some(RegexError::InvalidRepetition(3))
+        );
+    }
+
+    #[test]
+    fn count_states_without_building() {
+        for pattern in &[
+            "",
+            "^$",
+            "abc",
+            "a|b|",
+            "(a)(?:b)",
+            "a?b*c+",
+            "\\bx\\B",
+            "(ab){2,4}",
+            "^a{2,}$",
+            "[a-c]\\d.",
+            "^(a|bc){3}$",
+            "(x){0}",
+        ] {
+            let expression =
+                parse::parse(pattern, DEFAULT_REPETITION_LIMIT, false, Flags::default()).unwrap();
+            let built = compile_expression(&expression).state_count();
+            let extra = if expression.anchored_start { 1 } else { 2 };
+            assert_eq!(
+                state_count(&expression.node) + extra,
+                built,
+                "pattern {:?}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn too_complex() {
+        // each repetition is within the repetition limit, but together they
+        // make too many states
+        let pattern = "(?:a|b|c){3000}".repeat(5);
+        assert_eq!(try_compile(&pattern).err(), Some(RegexError::TooComplex));
+        assert!(try_compile(&"(?:a|b|c){3000}".repeat(4)).is_ok());
+        assert_eq!(RegexError::TooComplex.position(), None);
+
+        let nested = "((((a?){3}){3}){3}){3}";
+        assert!(Regex::new(nested).is_ok());
+        assert_eq!(
+            RegexBuilder::new().state_limit(300).build(nested).err(),
+            Some(RegexError::TooComplex)
+        );
+        assert!(RegexBuilder::new().state_limit(300).build("a{3}").is_ok());
+
+        // the limit covers every NFA a `Regex` compiles, which for `abc` is
+        // 5 states to search with and 4 each anchored at the start and at
+        // both ends
+        assert!(RegexBuilder::new().state_limit(13).build("abc").is_ok());
+        assert_eq!(
+            RegexBuilder::new().state_limit(12).build("abc").err(),
+            Some(RegexError::TooComplex)
+        );
+        // and the one to search with when anchored by the builder
+        let mut builder = RegexBuilder::new();
+        builder.anchored_start(true);
+        assert!(builder.state_limit(17).build("abc").is_ok());
+        assert_eq!(
+            builder.state_limit(16).build("abc").err(),
+            Some(RegexError::TooComplex)
+        );
+    }
+
+    #[test]
+    fn repetition_too_large() {
+        assert_eq!(
+            try_compile("a{1000000}").err(),
+            Some(RegexError::RepetitionTooLarge(1))
+        );
+        assert_eq!(
+            try_compile("xa{1000000,}").err(),
+            Some(RegexError::RepetitionTooLarge(2))
+        );
+        // nested repetitions multiply
+        assert_eq!(
+            try_compile("(a{200}){200}").err(),
+            Some(RegexError::RepetitionTooLarge(8))
+        );
+        assert_eq!(
+            try_compile("(ab){5001}").err(),
+            Some(RegexError::RepetitionTooLarge(4))
+        );
+        assert!(try_compile("a{10000}").is_ok());
+
+        assert_eq!(
+            try_compile_with_limit("a{11}", 10).err(),
+            Some(RegexError::RepetitionTooLarge(1))
+        );
+        assert!(try_compile_with_limit("a{10}", 10).is_ok());
+        assert!(try_compile_with_limit("^a{20000}$", 20_000)
+            .unwrap()
+            .run(&"a".repeat(20_000)));
+    }
+
+    #[test]
+    fn backreference() {
+        assert_eq!(
+            try_compile("(a)\\1").err(),
+            Some(RegexError::UnsupportedBackreference(3))
+        );
+        assert_eq!(
+            try_compile("\\0").err(),
+            Some(RegexError::UnsupportedBackreference(0))
+        );
+        // an escaped backslash before a digit is fine
+        assert!(compile_and_run("^\\\\1$", "\\1"));
+        assert!(compile_and_run("^\\d$", "1"));
+    }
+
+    #[test]
+    fn dangling_escape() {
+        assert_eq!(
+            try_compile("ab\\").err(),
+            Some(RegexError::DanglingEscape(2))
+        );
+        assert!(try_compile("ab\\\\").is_ok());
+    }
+
+    #[test]
+    fn word_boundary() {
+        assert!(compile_and_run("\\bcat\\b", "a cat sat"));
+        assert!(!compile_and_run("\\bcat\\b", "category"));
+        assert!(!compile_and_run("\\bcat\\b", "bobcat"));
+        // the start and end of the input count as non-word characters
+        assert!(compile_and_run("^\\bcat\\b$", "cat"));
+        assert!(compile_and_run("\\b", "a"));
+        assert!(!compile_and_run("\\b", ""));
+        assert!(!compile_and_run("\\b", "  "));
+        assert_eq!(compile("\\bcat\\b").find("category cat"), Some((9, 12)));
+        assert_eq!(compile("\\b").find(" a"), Some((1, 1)));
+    }
+
+    #[test]
+    fn not_word_boundary() {
+        assert!(compile_and_run("\\Bcat", "bobcat"));
+        assert!(!compile_and_run("\\Bcat", "cat"));
+        assert!(!compile_and_run("a\\B", "a"));
+        assert!(compile_and_run("a\\B", "ab"));
+        assert!(compile_and_run("^\\B$", ""));
+        assert!(compile_and_run("^(\\w\\B)+", "abc"));
+    }
+
+    #[test]
+    fn stacked_quantifiers() {
+        for pattern in &["a**", "a+*", "a?+", "a*?", "a+?", "a?*", "a*+"] {
+            let nfa = compile(&format!("^{}$", pattern));
+            assert_eq!(
+                nfa.state_count(),
+                compile("^a*$").state_count(),
+                "{}",
+                pattern
+            );
+            assert!(nfa.run(""), "{}", pattern);
+            assert!(nfa.run("aaa"), "{}", pattern);
+            assert!(!nfa.run("ab"), "{}", pattern);
+        }
+        assert!(compile_and_run("^a++$", "aa"));
+        assert!(!compile_and_run("^a++$", ""));
+        assert!(compile_and_run("^a??$", ""));
+        assert!(!compile_and_run("^a??$", "aa"));
+        assert!(compile_and_run("^(ab)+*$", "abab"));
+        assert_eq!(
+            compile("(a)+?").captures("aa"),
+            Some(vec![Some((0, 2)), Some((1, 2))])
+        );
+        // a counted repetition is still applied to what came before it
+        assert!(compile_and_run("^a*{2}$", "aaa"));
+        assert!(compile_and_run("^a{2}*$", "aaaa"));
+        assert!(!compile_and_run("^a{2}*$", "aaa"));
+    }
+
+    #[test]
+    fn nothing_to_repeat() {
+        for (expression, position) in &[
+            ("*", 0),
+            ("+a", 0),
+            ("?", 0),
+            ("*abc", 0),
+            ("{2}", 0),
+            ("^*a", 1),
+            ("a|?b", 2),
+            ("(+a)", 1),
+        ] {
+            assert_eq!(
+                try_compile(expression).err(),
+                Some(RegexError::NothingToRepeat(*position))
+            );
+        }
+    }
+
+    #[test]
+    fn never_matches() {
+        for pattern in &["a\\bb", "^\\b$", "x\\b\\B", "(a|b)\\b\\w", "\\w\\b[a-c]+"] {
+            assert_eq!(
+                try_compile(pattern).err(),
+                Some(RegexError::NeverMatches),
+                "{}",
+                pattern
+            );
+            assert_eq!(
+                Regex::new(pattern).err().as_ref(),
+                Some(&RegexError::NeverMatches)
+            );
+        }
+        // a negated class of every character reads nothing
+        assert_eq!(
+            try_compile("[^\\x00-\\x{10FFFF}]").err(),
+            Some(RegexError::NeverMatches)
+        );
+        assert!(try_compile("[^\\x00-\\x{10FFFE}]").is_ok());
+        for pattern in &["a\\b.", "a\\Bb", "(a|-)\\b\\w", "a\\bb|c", "[^a]\\bb"] {
+            assert!(try_compile(pattern).is_ok(), "{}", pattern);
+        }
+        assert_eq!(
+            RegexError::NeverMatches.to_string(),
+            "expression can never match"
+        );
+    }
+
+    #[test]
+    fn error_message() {
+        assert_eq!(
+            RegexError::UnbalancedBracket(3).to_string(),
+            "`[` is never closed at position 3"
+        );
+    }
+
+    #[test]
+    fn unbalanced_parenthesis() {
+        assert_eq!(
+            try_compile("(ab").err(),
+            Some(RegexError::UnbalancedParenthesis(0))
+        );
+        assert_eq!(
+            try_compile("ab)").err(),
+            Some(RegexError::UnbalancedParenthesis(2))
+        );
+        assert_eq!(
+            try_compile("a(b(c)").err(),
+            Some(RegexError::UnbalancedParenthesis(1))
+        );
+    }
+
+    #[test]
+    fn compile_dfa() {
+        let dfa = compile_to_dfa("^a[bc]+d$").unwrap();
+        assert!(dfa.run("abcbd"));
+        assert!(!dfa.run("ad"));
+        assert_eq!(
+            compile_to_dfa("a{2").err(),
+            Some(RegexError::InvalidRepetition(1))
+        );
+    }
+
+    #[test]
+    fn dfa_end_anchor() {
+        let dfa = compile_to_dfa("abc$").unwrap();
+        assert!(dfa.run("abc"));
+        assert!(dfa.run("xxabc"));
+        assert!(!dfa.run("abcd"));
+        assert!(!dfa.run("abcabcd"));
+        assert!(dfa.run("abcdabc"));
+
+        // without the anchor, anything may follow
+        let dfa = compile_to_dfa("abc").unwrap();
+        assert!(dfa.run("abcd"));
+
+        let dfa = compile_to_dfa("^abc$").unwrap();
+        assert!(dfa.run("abc"));
+        assert!(!dfa.run("xabc"));
+        assert!(!dfa.run("abcc"));
+        assert!(dfa.minimize().run("abc"));
+        assert!(!dfa.minimize().run("abcc"));
+
+        // an escaped `$` is read literally rather than anchoring
+        let dfa = compile_to_dfa("a\\$").unwrap();
+        assert!(dfa.run("a$b"));
+        assert!(!dfa.run("a"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let pattern = "^(ab|c)[x-z]+\\d?$";
+        let inputs = ["abx", "cyz1", "ab", "abx11", "dx"];
+
+        let nfa = compile(pattern);
+        let json = serde_json::to_string(&nfa).unwrap();
+        let loaded: Nfa = serde_json::from_str(&json).unwrap();
+        for input in &inputs {
+            assert_eq!(loaded.run(input), nfa.run(input), "input {:?}", input);
+        }
+
+        let dfa = nfa.to_dfa();
+        let json = serde_json::to_string(&dfa).unwrap();
+        let loaded: Dfa = serde_json::from_str(&json).unwrap();
+        for input in &inputs {
+            assert_eq!(loaded.run(input), nfa.run(input), "input {:?}", input);
+        }
+    }
+
+    #[test]
+    fn minimized_dfa_accepts_same_as_dfa() {
+        let patterns = [
+            "(a|b)*abb",
+            "^(ab|a)(b|c)*$",
+            "a+|b+|ab",
+            "^\\d\\w?$",
+            "^[ab]{1,3}c",
+        ];
+
+        // a small xorshift generator so the inputs are the same every run
+        let mut seed: u32 = 2_463_534_242;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed
+        };
+        let letters = ['a', 'b', 'c', '1', '_', ' '];
+        let inputs: Vec<String> = (0..300)
+            .map(|_| {
+                let length = random() % 8;
+                (0..length)
+                    .map(|_| letters[random() as usize % letters.len()])
+                    .collect()
+            })
+            .collect();
+
+        for pattern in &patterns {
+            let dfa = compile_to_dfa(pattern).unwrap();
+            let minimized = dfa.minimize();
+            for input in &inputs {
+                assert_eq!(
+                    minimized.run(input),
+                    dfa.run(input),
+                    "pattern {:?} on input {:?}",
+                    pattern,
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn dfa_accepts_same_as_nfa() {
+        let patterns = [
+            "",
+            "abc",
+            "^abc$",
+            "a.c",
+            "cat|dog",
+            "^(ab)*$",
+            "a+b?",
+            "[a-c]x",
+            "^\\d{2,3}$",
+            "\\w\\s\\W",
+            "^a|b$",
+            "x*y",
+            "^.$",
+            "\\bab\\b",
+            "^(\\Bb|a)*$",
+            "\\b1",
+        ];
+        let multiline_patterns = ["^a$", "^b", "a$\n?"];
+        let inputs = [
+            "",
+            "a",
+            "abc",
+            "xabcx",
+            "axc",
+            "dog",
+            "cog",
+            "abab",
+            "aab",
+            "bx",
+            "12",
+            "1234",
+            "a b",
+            "ab ab",
+            "b\na\n",
+            "a\nb",
+            "1",
+            "a1",
+            "a_b",
+            "xyz",
+            "y",
+            "\u{0663}\u{0664}",
+            "é",
+        ];
+
+        let nfas = patterns.iter().map(|pattern| (pattern, compile(pattern)));
+        let multiline_nfas = multiline_patterns.iter().map(|pattern| {
+            let flags = Flags {
+                multi_line: true,
+                ..Flags::default()
+            };
+            let expression = parse::parse(pattern, DEFAULT_REPETITION_LIMIT, false, flags).unwrap();
+            (pattern, compile_expression(&expression))
+        });
+        for (pattern, nfa) in nfas.chain(multiline_nfas) {
+            let dfa = nfa.to_dfa();
+            for input in &inputs {
+                assert_eq!(
+                    dfa.run(input),
+                    nfa.run(input),
+                    "pattern {:?} on input {:?}",
+                    pattern,
+                    input
+                );
+            }
+        }
     }
 }
@@ -1,116 +1,1594 @@
+use crate::automaton::Automaton;
+use crate::category::Category;
+use crate::dfa::Dfa;
+use crate::dot;
+use crate::error::BudgetExceeded;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
 
-#[derive(PartialEq, Eq, Hash)]
+/// How many states `run_parallel` needs to be in before it spreads them over threads.
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 64;
+
+/// A test that decides whether a character can be read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Predicate {
+    /// A numeric character, written `\d`.
+    Digit,
+    /// Anything but a numeric character, written `\D`.
+    NotDigit,
+    /// A word character `[A-Za-z0-9_]`, written `\w`.
+    Word,
+    /// Anything but a word character, written `\W`.
+    NotWord,
+    /// A whitespace character, written `\s`.
+    Space,
+    /// Anything but a whitespace character, written `\S`.
+    NotSpace,
+    /// Anything but a `\n`, written `.` outside of dotall mode.
+    NotNewline,
+    /// A character in a Unicode general category, written like `\p{L}`.
+    Category(Category),
+    /// Anything but a character in a Unicode general category,
+    /// written like `\P{L}`.
+    NotCategory(Category),
+}
+
+impl Predicate {
+    pub fn matches(&self, c: char) -> bool {
+        match self {
+            Predicate::Digit => c.is_numeric(),
+            Predicate::NotDigit => !c.is_numeric(),
+            Predicate::Word => is_word(c),
+            Predicate::NotWord => !is_word(c),
+            Predicate::Space => c.is_whitespace(),
+            Predicate::NotSpace => !c.is_whitespace(),
+            Predicate::NotNewline => c != '\n',
+            Predicate::Category(category) => category.contains(c),
+            Predicate::NotCategory(category) => !category.contains(c),
+        }
+    }
+
+    /// The shorthand the predicate is written as.
+    fn shorthand(&self) -> String {
+        let shorthand = match self {
+            Predicate::Digit => "\\d",
+            Predicate::NotDigit => "\\D",
+            Predicate::Word => "\\w",
+            Predicate::NotWord => "\\W",
+            Predicate::Space => "\\s",
+            Predicate::NotSpace => "\\S",
+            Predicate::NotNewline => "[^\\n]",
+            Predicate::Category(category) => return format!("\\p{{{}}}", category.letter()),
+            Predicate::NotCategory(category) => return format!("\\P{{{}}}", category.letter()),
+        };
+        String::from(shorthand)
+    }
+}
+
+/// How many characters there are, leaving out the surrogate code points.
+const CHAR_COUNT: usize = 0x11_0000 - 0x800;
+
+fn is_word(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// The word characters, as ranges.
+const WORD_RANGES: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+
+/// The ranges of characters `predicate` matches.
+///
+/// The standard library only tests one character at a time, so finding them
+/// means testing every character. That is only done the first time each
+/// predicate is asked for, and the ranges are kept for every time after.
+fn predicate_ranges(predicate: Predicate) -> Arc<[(char, char)]> {
+    type Ranges = HashMap<Predicate, Arc<[(char, char)]>>;
+    static RANGES: OnceLock<Mutex<Ranges>> = OnceLock::new();
+    let mut ranges = RANGES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    ranges
+        .entry(predicate)
+        .or_insert_with(|| char_ranges(('\0'..=char::MAX).filter(|c| predicate.matches(*c))).into())
+        .clone()
+}
+
+/// Gather sorted characters into ranges of consecutive ones.
+fn char_ranges(chars: impl IntoIterator<Item = char>) -> Vec<(char, char)> {
+    let mut ranges: Vec<(char, char)> = Vec::new();
+    for c in chars {
+        match ranges.last_mut() {
+            Some((_, high)) if *high as u32 + 1 == c as u32 => *high = c,
+            _ => ranges.push((c, c)),
+        }
+    }
+    ranges
+}
+
+/// A test on the characters either side of a position, which reads nothing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AssertKind {
+    /// A word character on one side and not the other, written `\b`.
+    WordBoundary,
+    /// Word characters on both sides or neither, written `\B`.
+    NotWordBoundary,
+    /// The start of the input or just after a `\n`, written `^` in multiline mode.
+    StartLine,
+    /// The end of the input or just before a `\n`, written `$` in multiline mode.
+    EndLine,
+    /// The start of the input, written `^` at the start of one of several
+    /// alternatives.
+    StartText,
+    /// The end of the input, written `$` at the end of one of several
+    /// alternatives.
+    EndText,
+}
+
+impl AssertKind {
+    fn holds(&self, around: Surroundings) -> bool {
+        let boundary = (around.before == Neighbour::Word) != (around.after == Neighbour::Word);
+        match self {
+            AssertKind::WordBoundary => boundary,
+            AssertKind::NotWordBoundary => !boundary,
+            AssertKind::StartLine => {
+                around.before == Neighbour::Edge || around.before == Neighbour::Newline
+            }
+            AssertKind::EndLine => {
+                around.after == Neighbour::Edge || around.after == Neighbour::Newline
+            }
+            AssertKind::StartText => around.before == Neighbour::Edge,
+            AssertKind::EndText => around.after == Neighbour::Edge,
+        }
+    }
+
+    /// The escape the assertion is written as.
+    fn shorthand(&self) -> &'static str {
+        match self {
+            AssertKind::WordBoundary => "\\b",
+            AssertKind::NotWordBoundary => "\\B",
+            AssertKind::StartLine => "^",
+            AssertKind::EndLine => "$",
+            AssertKind::StartText => "\\A",
+            AssertKind::EndText => "\\z",
+        }
+    }
+}
+
+/// What an assertion can tell apart about the character on one side of a position.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Neighbour {
+    /// There is no character, at the start or end of the input.
+    Edge,
+    Newline,
+    Word,
+    Other,
+}
+
+impl Neighbour {
+    fn of(c: Option<char>) -> Neighbour {
+        match c {
+            None => Neighbour::Edge,
+            Some('\n') => Neighbour::Newline,
+            Some(c) if is_word(c) => Neighbour::Word,
+            Some(_) => Neighbour::Other,
+        }
+    }
+}
+
+/// The characters either side of a position in the input.
+#[derive(Clone, Copy)]
+struct Surroundings {
+    before: Neighbour,
+    after: Neighbour,
+}
+
+impl Surroundings {
+    fn new(before: Option<char>, after: Option<char>) -> Surroundings {
+        Surroundings {
+            before: Neighbour::of(before),
+            after: Neighbour::of(after),
+        }
+    }
+
+    /// The surroundings of byte offset `position` in `input`.
+    fn at(input: &str, position: usize) -> Surroundings {
+        Surroundings::new(
+            input[..position].chars().next_back(),
+            input[position..].chars().next(),
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum NFAChar {
     Epsilon,
     If(char),
+    OneOf(BTreeSet<char>),
+    NoneOf(BTreeSet<char>),
+    Predicate(Predicate),
+    Any,
     Else,
+    Assert(AssertKind),
+    /// Read nothing, but note the position in the given capture slot.
+    Save(usize),
+    /// Read any character from the first to the second, inclusive.
+    Range(char, char),
+}
+
+impl NFAChar {
+    /// Describe the input, close to how it is written in an expression.
+    fn label(&self) -> String {
+        match self {
+            NFAChar::Epsilon => String::from("ε"),
+            NFAChar::If(c) => dot::char_label(*c),
+            NFAChar::OneOf(members) => format!("[{}]", members_label(members)),
+            NFAChar::NoneOf(members) => format!("[^{}]", members_label(members)),
+            NFAChar::Range(low, high) => format!("[{}]", dot::ranges_label(&[(*low, *high)])),
+            NFAChar::Predicate(predicate) => predicate.shorthand(),
+            NFAChar::Any => String::from("."),
+            NFAChar::Else => String::from("else"),
+            NFAChar::Assert(kind) => String::from(kind.shorthand()),
+            NFAChar::Save(slot) => format!("save {}", slot),
+        }
+    }
+
+    /// Returns true if the input reads `c`. Always false for `Else`, which
+    /// only reads what nothing else from the same state does, and for the
+    /// inputs that read nothing.
+    fn reads(&self, c: char) -> bool {
+        match self {
+            NFAChar::If(x) => *x == c,
+            NFAChar::OneOf(members) => members.contains(&c),
+            NFAChar::NoneOf(members) => !members.contains(&c),
+            NFAChar::Predicate(predicate) => predicate.matches(c),
+            NFAChar::Range(low, high) => (*low..=*high).contains(&c),
+            NFAChar::Any => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the input might read a character that assertions see
+    /// as `neighbour`, which is never `Edge`. Only returns false when it
+    /// certainly can't, giving true for `Else` and for anything outside of
+    /// ASCII that would take a search to be sure of.
+    fn may_read(&self, neighbour: Neighbour) -> bool {
+        let word_chars = || (0..128u8).map(char::from).filter(|c| is_word(*c));
+        match (self, neighbour) {
+            (NFAChar::If(c), _) => Neighbour::of(Some(*c)) == neighbour,
+            (NFAChar::OneOf(members), _) => {
+                members.iter().any(|c| Neighbour::of(Some(*c)) == neighbour)
+            }
+            (NFAChar::NoneOf(members), Neighbour::Newline) => !members.contains(&'\n'),
+            (NFAChar::NoneOf(members), Neighbour::Word) => {
+                word_chars().any(|c| !members.contains(&c))
+            }
+            (NFAChar::NoneOf(members), Neighbour::Other) => {
+                // every character but `\n` and the word characters
+                let others = CHAR_COUNT - 1 - word_chars().count();
+                members
+                    .iter()
+                    .filter(|c| Neighbour::of(Some(**c)) == Neighbour::Other)
+                    .count()
+                    < others
+            }
+            (NFAChar::Range(low, high), _) => {
+                (*low..=*high).any(|c| Neighbour::of(Some(c)) == neighbour)
+            }
+            (NFAChar::Predicate(predicate), Neighbour::Newline) => predicate.matches('\n'),
+            (NFAChar::Predicate(predicate), Neighbour::Word) => {
+                word_chars().any(|c| predicate.matches(c))
+            }
+            (NFAChar::Predicate(Predicate::Word), _) => false,
+            (NFAChar::Epsilon, _) | (NFAChar::Assert(_), _) | (NFAChar::Save(_), _) => false,
+            _ => true,
+        }
+    }
+}
+
+/// Describe the members of a character class, writing runs of consecutive
+/// characters as ranges.
+fn members_label(members: &BTreeSet<char>) -> String {
+    dot::ranges_label(&char_ranges(members.iter().copied()))
+}
+
+/// Maps a state and its input to every state it can move to.
+pub type Transition = HashMap<(u32, NFAChar), Vec<u32>>;
+
+/// Add a transition from state `from` to state `to` on `input`,
+/// keeping any transitions already made from `from` on `input`.
+pub fn add_transition(transitions: &mut Transition, from: u32, input: NFAChar, to: u32) {
+    let targets = transitions.entry((from, input)).or_default();
+    // each edge is built once, so one that is already there means two parts
+    // of an expression were given the same states
+    debug_assert!(!targets.contains(&to), "transition to q{} added twice", to);
+    targets.push(to);
+}
+
+/// One path being followed through the NFA while searching for a match.
+#[derive(Clone)]
+struct Thread {
+    state: u32,
+    /// Where the path last left the start state.
+    start: usize,
+    /// Where the path last entered an accepting state, if it is in one.
+    end: Option<usize>,
+    /// Where the path last went through a `Save` into each slot, or empty
+    /// if the search doesn't keep track of groups.
+    slots: Vec<Option<usize>>,
 }
 
-pub type Transition = HashMap<(u32, NFAChar), u32>;
+impl Thread {
+    /// Returns true if the thread is on its way to a match that starts
+    /// earlier than `other`'s, or that starts at the same place and has
+    /// gone on for longer.
+    fn is_longer_than(&self, other: &Thread) -> bool {
+        self.start < other.start || (self.start == other.start && self.end > other.end)
+    }
+}
 
-/// Represents a deterministic finite automaton
+/// Why a run of an NFA ended the way it did, from `Nfa::run_outcome`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchOutcome {
+    /// The NFA accepted the input.
+    Accepted,
+    /// No transition could read the character at byte offset `at`,
+    /// so the rest of the input was never read.
+    RejectedNoTransition { at: usize },
+    /// The whole input was read, but the NFA wasn't left in an accepting state.
+    RejectedNotAccepting,
+}
+
+/// Represents a nondeterministic finite automaton
 /// # Q: States
 /// We don't need to explicitly store this.
 /// # q0: Start state
 /// Always defined as 0.
 /// # Σ: Alphabet
 /// Defined as being all unicode characters.
+#[derive(Clone)]
 pub struct Nfa {
     /// # δ: Transition Function
-    /// Specifies a state and its input to get output state.
-    /// Uses `None` option to represent unconditional transition;
-    /// if `Some(x)` was also specified, it will look at that first.
+    /// Specifies a state and its input to get the output states.
+    /// Uses `Any` to represent unconditional transition.
+    /// Uses `Else` to represent transition on everything that nothing else
+    /// from the same state reads.
     transitions: Transition,
     /// # F: Accepting states
     /// Set of states that the NFA will accept in.
     accepting_states: HashSet<u32>,
+    /// The character class and predicate transitions out of each state,
+    /// since these can't be looked up by the character being read.
+    classes: HashMap<u32, Vec<(NFAChar, Vec<u32>)>>,
+    /// The assertion and save transitions out of each state, which are
+    /// followed like epsilon transitions.
+    zero_width: HashMap<u32, Vec<(NFAChar, Vec<u32>)>>,
+    /// How many capture slots the `Save` transitions use.
+    slot_count: usize,
+    /// Whether some transition leads back to the start state, which lets a
+    /// match begin anywhere in the input.
+    can_restart: bool,
+}
+
+impl Nfa {
+    /// Create a new NFA
+    ///
+    /// Give a transition function in the form of a `Transition`.
+    /// Use `Any` as the input character to add transition on everything,
+    /// alongside any `If(x)` transitions.
+    /// Use `Else` as the input character to add transition on everything after
+    /// the `If(x)` transitions.
+    ///
+    /// Use `Assert(x)` as the input character to add transition that reads
+    /// nothing, taken only where the assertion holds.
+    /// Use `Save(x)` as the input character to add transition that reads
+    /// nothing and notes where it was taken in capture slot `x`.
+    ///
+    /// Give the set of accepting states in the form of a `HashSet`.
+    /// Refer to states by an integer.
+    pub fn new(transitions: Transition, accepting_states: HashSet<u32>) -> Nfa {
+        let mut classes: HashMap<u32, Vec<(NFAChar, Vec<u32>)>> = HashMap::new();
+        let mut zero_width: HashMap<u32, Vec<(NFAChar, Vec<u32>)>> = HashMap::new();
+        let mut slot_count = 0;
+        for ((from, input), to) in &transitions {
+            let index = match input {
+                NFAChar::OneOf(_)
+                | NFAChar::NoneOf(_)
+                | NFAChar::Predicate(_)
+                | NFAChar::Range(..) => &mut classes,
+                NFAChar::Assert(_) => &mut zero_width,
+                NFAChar::Save(slot) => {
+                    slot_count = slot_count.max(slot + 1);
+                    &mut zero_width
+                }
+                _ => continue,
+            };
+            index
+                .entry(*from)
+                .or_default()
+                .push((input.clone(), to.clone()));
+        }
+        // the transitions came out of a hash map in no particular order, so
+        // order them by where they lead to prefer the same ones every time
+        for index in [&mut classes, &mut zero_width] {
+            for transitions in index.values_mut() {
+                transitions.sort_by_key(|(_, to)| to.iter().min().copied());
+            }
+        }
+
+        let can_restart = transitions.values().any(|targets| targets.contains(&0));
+        Nfa {
+            transitions,
+            accepting_states,
+            classes,
+            zero_width,
+            slot_count,
+            can_restart,
+        }
+    }
+
+    /// Find the states reached from `state` without reading anything:
+    /// through epsilon transitions, saves, and assertions that hold in `around`.
+    /// Each state comes with the capture slot saved on the way, if any.
+    fn zero_width_targets(
+        &self,
+        state: u32,
+        around: Surroundings,
+    ) -> impl Iterator<Item = (u32, Option<usize>)> + '_ {
+        let epsilon = self
+            .transitions
+            .get(&(state, NFAChar::Epsilon))
+            .into_iter()
+            .flatten()
+            .map(|to| (*to, None));
+        let zero_width = self
+            .zero_width
+            .get(&state)
+            .into_iter()
+            .flatten()
+            .filter_map(move |(input, to)| match input {
+                NFAChar::Assert(kind) if kind.holds(around) => Some((to, None)),
+                NFAChar::Save(slot) => Some((to, Some(*slot))),
+                _ => None,
+            })
+            .flat_map(|(to, slot)| to.iter().map(move |to| (*to, slot)));
+        epsilon.chain(zero_width)
+    }
+
+    /// Returns true if some transitions depend on what is either side of them.
+    fn has_assertions(&self) -> bool {
+        self.zero_width
+            .values()
+            .flatten()
+            .any(|(input, _)| matches!(input, NFAChar::Assert(_)))
+    }
+
+    /// Find all states connected through epsilon transitions
+    /// to given state: curr.
+    ///
+    /// Uses a stack of states still to follow rather than recursion,
+    /// so long chains of epsilon transitions can't overflow the call stack.
+    fn follow_epilon_transition(
+        &self,
+        current_states: &mut BTreeSet<u32>,
+        curr: u32,
+        around: Surroundings,
+    ) {
+        let mut to_follow = vec![curr];
+        while let Some(state) = to_follow.pop() {
+            for (new_state, _) in self.zero_width_targets(state, around) {
+                // only follow states we haven't already been to
+                if current_states.insert(new_state) {
+                    to_follow.push(new_state);
+                }
+            }
+        }
+    }
+
+    /// Find the states reached from `state` by a character class or predicate
+    /// that accepts `c`.
+    fn follow_class_transition(&self, state: u32, c: char) -> Vec<u32> {
+        let classes = match self.classes.get(&state) {
+            Some(classes) => classes,
+            None => return Vec::new(),
+        };
+
+        classes
+            .iter()
+            .filter(|(input, _)| input.reads(c))
+            .flat_map(|(_, to)| to)
+            .copied()
+            .collect()
+    }
+
+    /// Find every state reached from `state` by reading `c`.
+    /// `Else` transitions are only taken if nothing else from `state` accepts `c`.
+    fn step(&self, state: u32, c: char) -> Vec<u32> {
+        let mut new_states = self.follow_class_transition(state, c);
+        if let Some(targets) = self.transitions.get(&(state, NFAChar::If(c))) {
+            new_states.splice(0..0, targets.iter().copied());
+        }
+        if let Some(targets) = self.transitions.get(&(state, NFAChar::Any)) {
+            new_states.extend(targets);
+        }
+
+        if new_states.is_empty() {
+            if let Some(targets) = self.transitions.get(&(state, NFAChar::Else)) {
+                new_states.extend(targets);
+            }
+        }
+        new_states
+    }
+
+    /// Move `thread` into state `to` at byte offset `position`.
+    fn advance(&self, thread: &Thread, to: u32, position: usize) -> Thread {
+        // coming back to the start state means starting the match over
+        let (start, slots) = if to == 0 {
+            (position, vec![None; thread.slots.len()])
+        } else {
+            (thread.start, thread.slots.clone())
+        };
+        let end = match (self.accepting_states.contains(&to), thread.end) {
+            (false, _) => None,
+            (true, None) => Some(position),
+            (true, end) => end,
+        };
+
+        Thread {
+            state: to,
+            start,
+            end,
+            slots,
+        }
+    }
+
+    /// Add `thread` and every thread connected to it through epsilon transitions,
+    /// in order of preference, skipping states that already have a thread.
+    ///
+    /// If `longest` is set, a thread that is longer than the one a state
+    /// already has takes its place instead of being skipped.
+    fn add_thread(
+        &self,
+        threads: &mut Vec<Thread>,
+        visited: &mut HashMap<u32, usize>,
+        thread: Thread,
+        position: usize,
+        around: Surroundings,
+        longest: bool,
+    ) {
+        let mut stack = vec![thread];
+        while let Some(thread) = stack.pop() {
+            let replaced = match visited.get(&thread.state) {
+                None => None,
+                Some(index) if longest && thread.is_longer_than(&threads[*index]) => Some(*index),
+                Some(_) => continue,
+            };
+
+            let targets: Vec<_> = self.zero_width_targets(thread.state, around).collect();
+            for (to, slot) in targets.into_iter().rev() {
+                let mut next_thread = self.advance(&thread, to, position);
+                if let Some(slot) = slot {
+                    // a thread that isn't keeping groups has no slots
+                    if let Some(saved) = next_thread.slots.get_mut(slot) {
+                        *saved = Some(position);
+                    }
+                }
+                stack.push(next_thread);
+            }
+            match replaced {
+                Some(index) => threads[index] = thread,
+                None => {
+                    visited.insert(thread.state, threads.len());
+                    threads.push(thread);
+                }
+            }
+        }
+    }
+
+    /// Find the first match of the NFA in `input`.
+    ///
+    /// Returns the byte offsets of the start and end of the match.
+    /// The match that starts earliest is preferred, then the one
+    /// found by following transitions in the order they were added.
+    pub fn find(&self, input: &str) -> Option<(usize, usize)> {
+        self.find_at(input, 0)
+    }
+
+    /// Find the first match of the NFA in `input` that starts at or after
+    /// byte offset `from`.
+    pub fn find_at(&self, input: &str, from: usize) -> Option<(usize, usize)> {
+        let thread = self.search(input, from, false, false)?;
+        Some((thread.start, thread.end?))
+    }
+
+    /// Find the longest of the matches of the NFA in `input` that start
+    /// earliest, as POSIX does, rather than the one found by following
+    /// transitions in the order they were added.
+    ///
+    /// So `a|ab` on `"ab"` matches `ab`, where `find` would match `a`.
+    pub fn find_longest(&self, input: &str) -> Option<(usize, usize)> {
+        self.find_longest_at(input, 0)
+    }
+
+    /// Find the longest match like `find_longest`, starting at or after
+    /// byte offset `from`.
+    pub fn find_longest_at(&self, input: &str, from: usize) -> Option<(usize, usize)> {
+        let thread = self.search(input, from, true, false)?;
+        Some((thread.start, thread.end?))
+    }
+
+    /// Find the first match of the NFA in `input`, along with the span each
+    /// capture group matched.
+    ///
+    /// Index 0 is the whole match, and index `n` is the span from the
+    /// `Save(2 * n)` to the `Save(2 * n + 1)` the match went through.
+    /// A group the match didn't go through is `None`.
+    pub fn captures(&self, input: &str) -> Option<Vec<Option<(usize, usize)>>> {
+        self.captures_at(input, 0)
+    }
+
+    /// Find the first match of the NFA in `input` that starts at or after
+    /// byte offset `from`, along with the span each capture group matched.
+    pub fn captures_at(&self, input: &str, from: usize) -> Option<Vec<Option<(usize, usize)>>> {
+        self.groups(self.search(input, from, false, true)?)
+    }
+
+    /// Find the longest match like `find_longest_at`, along with the span
+    /// each capture group matched.
+    pub fn captures_longest_at(
+        &self,
+        input: &str,
+        from: usize,
+    ) -> Option<Vec<Option<(usize, usize)>>> {
+        self.groups(self.search(input, from, true, true)?)
+    }
+
+    /// The span of the match `thread` found and of each capture group in it.
+    fn groups(&self, thread: Thread) -> Option<Vec<Option<(usize, usize)>>> {
+        let mut groups = vec![Some((thread.start, thread.end?))];
+        for group in 1..self.slot_count.div_ceil(2) {
+            let start = thread.slots[2 * group];
+            let end = thread.slots.get(2 * group + 1).copied().flatten();
+            groups.push(start.zip(end));
+        }
+        Some(groups)
+    }
+
+    /// Find the match of the NFA in `input` that starts exactly at byte
+    /// offset `from`, returning the offset where it ends.
+    ///
+    /// Unlike `find_at`, the NFA is run from `from` even if it has no way
+    /// back to the start state, so an NFA without one only matches there.
+    pub fn find_anchored_at(&self, input: &str, from: usize) -> Option<usize> {
+        self.anchored_end(self.search_from(input, from, false, false)?, from)
+    }
+
+    /// Find the longest match that starts exactly at byte offset `from`,
+    /// like `find_anchored_at` but preferring matches like `find_longest`.
+    pub fn find_anchored_longest_at(&self, input: &str, from: usize) -> Option<usize> {
+        self.anchored_end(self.search_from(input, from, true, false)?, from)
+    }
+
+    /// Find the first match like `find_at`, or the longest like
+    /// `find_longest_at` if `longest` is set, along with whether more input
+    /// after `input` could still change it.
+    pub(crate) fn find_open_at(
+        &self,
+        input: &str,
+        from: usize,
+        longest: bool,
+    ) -> Option<(usize, usize, bool)> {
+        if from > 0 && !self.can_restart {
+            return None;
+        }
+        let (thread, open) = self.search_open(input, from, longest, false);
+        let thread = thread?;
+        Some((thread.start, thread.end?, open))
+    }
+
+    /// The end of the match `thread` found, if it starts at `from`.
+    fn anchored_end(&self, thread: Thread, from: usize) -> Option<usize> {
+        if thread.start == from {
+            thread.end
+        } else {
+            None
+        }
+    }
+
+    /// Follow every path through the NFA from byte offset `from`, returning
+    /// the one that found the preferred match.
+    ///
+    /// If `longest` is set, the preferred match is the longest of those that
+    /// start earliest; otherwise it is the first one found by following
+    /// transitions in order. Where the groups matched is only kept track of
+    /// if `groups` is set, which saves copying it along every path.
+    fn search(&self, input: &str, from: usize, longest: bool, groups: bool) -> Option<Thread> {
+        // without a way back to the start state, a match has to begin
+        // where the input does
+        if from > 0 && !self.can_restart {
+            return None;
+        }
+        self.search_from(input, from, longest, groups)
+    }
+
+    /// Follow every path through the NFA as if the input began at byte
+    /// offset `from`, returning the one that found the preferred match.
+    fn search_from(&self, input: &str, from: usize, longest: bool, groups: bool) -> Option<Thread> {
+        self.search_open(input, from, longest, groups).0
+    }
+
+    /// Search like `search_from`, along with whether the input ran out while
+    /// some path that could still change the match was left, so that more
+    /// input might give a different one.
+    fn search_open(
+        &self,
+        input: &str,
+        from: usize,
+        longest: bool,
+        groups: bool,
+    ) -> (Option<Thread>, bool) {
+        let mut threads = Vec::new();
+        let start = Thread {
+            state: 0,
+            start: from,
+            end: None,
+            slots: vec![None; if groups { self.slot_count } else { 0 }],
+        };
+        self.add_thread(
+            &mut threads,
+            &mut HashMap::new(),
+            self.advance(&start, 0, from),
+            from,
+            Surroundings::at(input, from),
+            longest,
+        );
+        // the preferred match seen so far, since a path can leave an
+        // accepting state and never reach another
+        let mut best: Option<Thread> = None;
+
+        for (i, c) in input[from..].char_indices() {
+            best = Nfa::settle(best, &mut threads, longest);
+            if !longest && threads[0].end.is_some() {
+                // no path that is preferred over this match is left
+                return (best, false);
+            }
+            let position = from + i + c.len_utf8();
+            let around = Surroundings::new(Some(c), input[position..].chars().next());
+            let mut next_threads = Vec::new();
+            let mut visited = HashMap::new();
+
+            // paths that start over are added last so earlier matches win
+            let mut restarts = Vec::new();
+            for thread in &threads {
+                for to in self.step(thread.state, c) {
+                    // staying in an accepting state only finds the same match
+                    // again, which `best` already has
+                    if longest && to == thread.state && thread.end.is_some() {
+                        continue;
+                    }
+                    let next_thread = self.advance(thread, to, position);
+                    if to == 0 {
+                        // a match that starts later is never preferred
+                        if best.is_none() {
+                            restarts.push(next_thread);
+                        }
+                    } else {
+                        self.add_thread(
+                            &mut next_threads,
+                            &mut visited,
+                            next_thread,
+                            position,
+                            around,
+                            longest,
+                        );
+                    }
+                }
+            }
+            for thread in restarts {
+                self.add_thread(
+                    &mut next_threads,
+                    &mut visited,
+                    thread,
+                    position,
+                    around,
+                    longest,
+                );
+            }
+
+            if next_threads.is_empty() {
+                return (best, false);
+            }
+            threads = next_threads;
+        }
+
+        let best = Nfa::settle(best, &mut threads, longest);
+        let open = threads.iter().any(|thread| self.can_go_on(thread.state));
+        (best, open)
+    }
+
+    /// Returns true if reading more from `state` could lead somewhere new:
+    /// anywhere but back to `state` if it accepts, since a path that stays
+    /// there keeps the match it has, or through an assertion that might hold
+    /// once there is more to read.
+    fn can_go_on(&self, state: u32) -> bool {
+        self.transitions
+            .iter()
+            .filter(|((from, _), _)| *from == state)
+            .any(|((_, input), targets)| match input {
+                // the states these lead to have paths of their own
+                NFAChar::Epsilon | NFAChar::Save(_) => false,
+                NFAChar::Assert(_) => true,
+                _ => {
+                    !self.accepting_states.contains(&state) || targets.iter().any(|to| *to != state)
+                }
+            })
+    }
+
+    /// Update `best` with the matches `threads` have found, and drop the
+    /// threads that can no longer find a match that is preferred over it.
+    fn settle(best: Option<Thread>, threads: &mut Vec<Thread>, longest: bool) -> Option<Thread> {
+        if longest {
+            let best = Nfa::longest(best, threads)?;
+            threads.retain(|thread| thread.start <= best.start);
+            return Some(best);
+        }
+        // threads are in order of preference, so the first accepting one
+        // beats every one after it
+        match threads.iter().position(|thread| thread.end.is_some()) {
+            Some(index) => {
+                threads.truncate(index + 1);
+                Some(threads[index].clone())
+            }
+            None => best,
+        }
+    }
+
+    /// The longest of `best` and the threads that are in an accepting state.
+    fn longest(best: Option<Thread>, threads: &[Thread]) -> Option<Thread> {
+        threads
+            .iter()
+            .filter(|thread| thread.end.is_some())
+            .fold(best, |best, thread| match best {
+                Some(best) if !thread.is_longer_than(&best) => Some(best),
+                _ => Some(thread.clone()),
+            })
+    }
+
+    /// Run the NFA
+    ///
+    /// give a unicode string as an input.
+    /// return true if the NFA accepts.
+    /// return false if the NFA rejects.
+    pub fn run(&self, input: &str) -> bool {
+        self.run_iter(input.chars())
+    }
+
+    /// Find the shortest prefix of `input` that the NFA accepts.
+    ///
+    /// Returns the byte offset of the end of the prefix,
+    /// stopping as soon as it is found.
+    pub fn shortest_match(&self, input: &str) -> Option<usize> {
+        let mut matcher = self.matcher();
+        for (i, c) in input.char_indices() {
+            if matcher.accepts_before(Some(c)) {
+                return Some(i);
+            }
+            matcher.push(c);
+            if matcher.is_dead() {
+                return None;
+            }
+        }
+        if matcher.is_accepting() {
+            Some(input.len())
+        } else {
+            None
+        }
+    }
+
+    /// Run the NFA on input that might not be valid UTF-8.
+    ///
+    /// Each byte is read as the character with the same value, so a pattern
+    /// written in ASCII matches the same bytes it would in a string, and `.`
+    /// matches any byte but `\n`.
+    pub fn run_bytes(&self, input: &[u8]) -> bool {
+        self.run_iter(input.iter().map(|byte| char::from(*byte)))
+    }
+
+    /// Run the NFA on characters as they come from `input`,
+    /// without needing them all at once.
+    pub fn run_iter<I: Iterator<Item = char>>(&self, input: I) -> bool {
+        self.outcome(input) == MatchOutcome::Accepted
+    }
+
+    /// Run the NFA like `run`, but say why it rejected the input if it did.
+    pub fn run_outcome(&self, input: &str) -> MatchOutcome {
+        self.outcome(input.chars())
+    }
+
+    /// Run the NFA like `run`, giving up once more than `max_steps` states
+    /// have been stepped.
+    ///
+    /// Each character read steps every state the NFA is in, so the steps
+    /// taken grow with both the input and how many paths are being followed.
+    pub fn run_with_budget(&self, input: &str, max_steps: usize) -> Result<bool, BudgetExceeded> {
+        let mut matcher = self.matcher();
+        let mut steps = 0;
+        for c in input.chars() {
+            steps += matcher.step(c);
+            if steps > max_steps {
+                return Err(BudgetExceeded);
+            }
+            if matcher.is_dead() {
+                return Ok(false);
+            }
+            if matcher.will_accept() {
+                return Ok(true);
+            }
+        }
+        Ok(matcher.is_accepting())
+    }
+
+    /// Run the NFA on `input`, counting byte offsets as if it were UTF-8.
+    fn outcome<I: Iterator<Item = char>>(&self, input: I) -> MatchOutcome {
+        let mut matcher = self.matcher();
+        let mut offset = 0;
+        for c in input {
+            matcher.push(c);
+            // exit if there were no valid transitions
+            if matcher.is_dead() {
+                return MatchOutcome::RejectedNoTransition { at: offset };
+            }
+            // or once the rest of the input can't change the outcome
+            if matcher.will_accept() {
+                return MatchOutcome::Accepted;
+            }
+            offset += c.len_utf8();
+        }
+        if matcher.is_accepting() {
+            MatchOutcome::Accepted
+        } else {
+            MatchOutcome::RejectedNotAccepting
+        }
+    }
+
+    /// Start running the NFA on input that will be given a character at a time.
+    pub fn matcher(&self) -> Matcher<'_> {
+        let mut states = BTreeSet::new();
+        states.insert(0);
+        Matcher {
+            nfa: self,
+            states,
+            last: None,
+        }
+    }
+
+    /// Run the NFA, spreading the current states over several threads.
+    ///
+    /// Accepts exactly the same input as `run`. Since reading a character only
+    /// gives a set of next states, the order the threads finish in doesn't
+    /// matter. Steps with only a few current states are taken on the calling
+    /// thread, where sharing out the work would cost more than it saves.
+    #[cfg(feature = "parallel")]
+    pub fn run_parallel(&self, input: &str) -> bool {
+        use rayon::prelude::*;
+
+        let mut matcher = self.matcher();
+        for c in input.chars() {
+            if matcher.states.len() < PARALLEL_THRESHOLD {
+                matcher.push(c);
+            } else {
+                let around = Surroundings::new(matcher.last, Some(c));
+                matcher.states = matcher
+                    .states
+                    .par_iter()
+                    .fold(BTreeSet::new, |mut next_states, state| {
+                        let mut closed = BTreeSet::new();
+                        closed.insert(*state);
+                        self.follow_epilon_transition(&mut closed, *state, around);
+                        for state in closed {
+                            next_states.extend(self.step(state, c));
+                        }
+                        next_states
+                    })
+                    .reduce(BTreeSet::new, |mut next_states, other| {
+                        next_states.extend(other);
+                        next_states
+                    });
+                matcher.last = Some(c);
+            }
+
+            // exit if there were no valid transitions
+            if matcher.is_dead() {
+                return false;
+            }
+            if matcher.will_accept() {
+                return true;
+            }
+        }
+        matcher.is_accepting()
+    }
+
+    /// Count the states, which are the start state and every state that a
+    /// transition leads from or to or that accepts.
+    pub fn state_count(&self) -> usize {
+        let mut states: HashSet<u32> = self.accepting_states.iter().copied().collect();
+        states.insert(0);
+        for ((from, _), targets) in &self.transitions {
+            states.insert(*from);
+            states.extend(targets);
+        }
+        states.len()
+    }
+
+    /// Count the transitions, with one for each state a transition leads to.
+    pub fn transition_count(&self) -> usize {
+        self.transitions.values().map(Vec::len).sum()
+    }
+
+    /// The states the NFA accepts in.
+    pub fn accepting_states(&self) -> &HashSet<u32> {
+        &self.accepting_states
+    }
+
+    /// Build the NFA that accepts an input if it splits into a part that `a`
+    /// accepts followed by a part that `b` accepts.
+    ///
+    /// Each state of `b` is numbered after those of `a`, and every accepting
+    /// state of `a` moves on to the start of `b` without reading. The groups
+    /// of `b` are numbered after those of `a`. Assertions see the whole
+    /// input, so a `$` ending `a` only holds if `b` reads nothing, and a `^`
+    /// starting `b` only if `a` read nothing.
+    pub fn concat(a: &Nfa, b: &Nfa) -> Nfa {
+        let (mut transitions, _) = a.renumbered(0, 0, 0);
+        let b_start = a.end_state();
+        // going back to the start of `b` only starts its part over, so the
+        // match still starts where `a` did
+        let (b_transitions, accepting_states) = b.renumbered(b_start, a.group_count(), b_start);
+        transitions.extend(b_transitions);
+        for state in &a.accepting_states {
+            add_transition(&mut transitions, *state, NFAChar::Epsilon, b_start);
+        }
+        Nfa::new(transitions, accepting_states)
+    }
+
+    /// Build the NFA that accepts whatever `a` or `b` accepts, preferring the
+    /// matches of `a`.
+    ///
+    /// A new start state moves on to the starts of both without reading, with
+    /// the states of `a` numbered after it and those of `b` after them. The
+    /// groups of `b` are numbered after those of `a`. Going back to the start
+    /// of either leads to the new start state, so a match that starts over
+    /// tries both again.
+    pub fn union(a: &Nfa, b: &Nfa) -> Nfa {
+        let (mut transitions, mut accepting_states) = a.renumbered(1, 0, 0);
+        let b_start = 1 + a.end_state();
+        let (b_transitions, b_accepting) = b.renumbered(b_start, a.group_count(), 0);
+        transitions.extend(b_transitions);
+        accepting_states.extend(b_accepting);
+        add_transition(&mut transitions, 0, NFAChar::Epsilon, 1);
+        add_transition(&mut transitions, 0, NFAChar::Epsilon, b_start);
+        Nfa::new(transitions, accepting_states)
+    }
+
+    /// One more than the largest state, so the states from it on are unused.
+    fn end_state(&self) -> u32 {
+        self.transitions
+            .iter()
+            .flat_map(|((from, _), targets)| targets.iter().chain(Some(from)))
+            .chain(&self.accepting_states)
+            .max()
+            .map_or(1, |state| state + 1)
+    }
+
+    /// How many capture groups the NFA saves positions for.
+    fn group_count(&self) -> usize {
+        self.slot_count.div_ceil(2).saturating_sub(1)
+    }
+
+    /// The transitions and accepting states with `offset` added to every
+    /// state and `groups` to the index of every group, and with every
+    /// transition back to the start state leading to `restart` instead.
+    fn renumbered(&self, offset: u32, groups: usize, restart: u32) -> (Transition, HashSet<u32>) {
+        let transitions = self
+            .transitions
+            .iter()
+            .map(|((from, input), targets)| {
+                let input = match input {
+                    NFAChar::Save(slot) => NFAChar::Save(slot + 2 * groups),
+                    input => input.clone(),
+                };
+                let targets = targets
+                    .iter()
+                    .map(|to| if *to == 0 { restart } else { to + offset })
+                    .collect();
+                ((from + offset, input), targets)
+            })
+            .collect();
+        let accepting_states = self.accepting_states.iter().map(|s| s + offset).collect();
+        (transitions, accepting_states)
+    }
+
+    /// The characters read by single-character transitions.
+    /// Characters that are only read by a class or predicate are left out.
+    pub fn alphabet(&self) -> HashSet<char> {
+        self.transitions
+            .keys()
+            .filter_map(|(_, input)| match input {
+                NFAChar::If(c) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Write the NFA as a Graphviz `digraph`, with each edge labelled by its input.
+    pub fn to_dot(&self) -> String {
+        let edges = self
+            .transitions
+            .iter()
+            .flat_map(|((from, input), targets)| {
+                targets.iter().map(move |to| (*from, *to, input.label()))
+            })
+            .collect();
+        dot::digraph(BTreeSet::new(), &self.accepting_states, edges)
+    }
+
+    /// Split the characters into classes that every transition reads the
+    /// same way, each given as sorted ranges, so that building a DFA only
+    /// takes reading one character from each class rather than every
+    /// character there is.
+    ///
+    /// When there are assertions, the characters in a class also look the
+    /// same to them.
+    fn dfa_classes(&self) -> Vec<Vec<(char, char)>> {
+        let has_assertions = self.has_assertions();
+
+        // where a run of characters that might be read differently from the
+        // one before it starts, keeping the surrogate code points apart
+        let mut cuts: BTreeSet<u32> = [0, 0xD800, 0xE000, 0x11_0000].iter().copied().collect();
+        let mut add_cuts = |ranges: &[(char, char)]| {
+            for (low, high) in ranges {
+                cuts.insert(*low as u32);
+                cuts.insert(*high as u32 + 1);
+            }
+        };
+        let mut inputs = HashSet::new();
+        let mut predicates = HashSet::new();
+        for (_, input) in self.transitions.keys() {
+            match input {
+                NFAChar::If(c) => add_cuts(&[(*c, *c)]),
+                NFAChar::OneOf(members) | NFAChar::NoneOf(members) => {
+                    add_cuts(&char_ranges(members.iter().copied()))
+                }
+                NFAChar::Range(low, high) => add_cuts(&[(*low, *high)]),
+                // a predicate and its negation single out the same characters
+                NFAChar::Predicate(predicate) => match predicate {
+                    Predicate::Digit | Predicate::NotDigit => {
+                        predicates.insert(Predicate::Digit);
+                    }
+                    Predicate::Word | Predicate::NotWord => add_cuts(WORD_RANGES),
+                    Predicate::Space | Predicate::NotSpace => {
+                        predicates.insert(Predicate::Space);
+                    }
+                    Predicate::NotNewline => add_cuts(&[('\n', '\n')]),
+                    Predicate::Category(category) | Predicate::NotCategory(category) => {
+                        predicates.insert(Predicate::Category(*category));
+                    }
+                },
+                _ => continue,
+            }
+            inputs.insert(input);
+        }
+        // assertions tell newlines and word characters apart from the rest
+        if has_assertions {
+            add_cuts(&[('\n', '\n')]);
+            add_cuts(WORD_RANGES);
+        }
+        for predicate in predicates {
+            add_cuts(&predicate_ranges(predicate));
+        }
+
+        // put the runs that are read the same way in the same class
+        let inputs: Vec<&NFAChar> = inputs.into_iter().collect();
+        let mut classes: Vec<Vec<(char, char)>> = Vec::new();
+        let mut ids = HashMap::new();
+        let cuts: Vec<u32> = cuts.into_iter().collect();
+        for run in cuts.windows(2) {
+            if run[0] == 0xD800 {
+                continue;
+            }
+            let low = char::from_u32(run[0]).unwrap();
+            let high = char::from_u32(run[1] - 1).unwrap();
+            let read: Vec<bool> = inputs.iter().map(|input| input.reads(low)).collect();
+            let neighbour = if has_assertions {
+                Neighbour::of(Some(low))
+            } else {
+                Neighbour::Other
+            };
+            let id = *ids.entry((read, neighbour)).or_insert_with(|| {
+                classes.push(Vec::new());
+                classes.len() - 1
+            });
+            match classes[id].last_mut() {
+                Some((_, last)) if *last as u32 + 1 == run[0] => *last = high,
+                _ => classes[id].push((low, high)),
+            }
+        }
+        classes
+    }
+
+    /// Find every state in `states` or connected to them without reading,
+    /// in the surroundings `around`.
+    fn close_states(&self, states: &BTreeSet<u32>, around: Surroundings) -> BTreeSet<u32> {
+        let mut closed = states.clone();
+        for state in states {
+            self.follow_epilon_transition(&mut closed, *state, around);
+        }
+        closed
+    }
+
+    /// Convert the NFA into a DFA that accepts the same input.
+    ///
+    /// Uses the subset construction: each state of the DFA stands for the set
+    /// of NFA states that the same input can lead to. Characters that every
+    /// transition reads the same way are put in one class, and each state of
+    /// the DFA only moves on one character from each class.
+    ///
+    /// Whether an assertion holds depends on the next character as well as the
+    /// last one, so when there are assertions each DFA state also remembers the
+    /// last character read, and only follows transitions that read nothing
+    /// once it knows what comes next.
+    pub fn to_dfa(&self) -> Dfa {
+        let mut classes = self.dfa_classes();
+        // the class with the most ranges is read by the `None` transitions,
+        // so that its ranges don't have to be looked up
+        let otherwise_class = (0..classes.len())
+            .rev()
+            .max_by_key(|class| classes[*class].len())
+            .unwrap();
+        let otherwise_char = classes.remove(otherwise_class)[0].0;
+        let mut ranges: Vec<(char, char, u32)> = classes
+            .iter()
+            .enumerate()
+            .flat_map(|(id, class)| {
+                class
+                    .iter()
+                    .map(move |(low, high)| (*low, *high, id as u32))
+            })
+            .collect();
+        ranges.sort_unstable();
+        let has_assertions = self.has_assertions();
+
+        // the states reached after reading up to a character
+        let key = |states: BTreeSet<u32>, before: Neighbour| {
+            if has_assertions {
+                (states, before)
+            } else {
+                // nothing depends on the surroundings, so close the set straight away
+                let around = Surroundings {
+                    before,
+                    after: Neighbour::Edge,
+                };
+                (self.close_states(&states, around), Neighbour::Edge)
+            }
+        };
+        let move_states = |(states, before): &(BTreeSet<u32>, Neighbour), c: char| {
+            let around = Surroundings {
+                before: *before,
+                after: Neighbour::of(Some(c)),
+            };
+            let mut next_states = BTreeSet::new();
+            for state in self.close_states(states, around) {
+                next_states.extend(self.step(state, c));
+            }
+            key(next_states, around.after)
+        };
+
+        let mut start = BTreeSet::new();
+        start.insert(0);
+        let mut ids = HashMap::new();
+        let mut sets = vec![key(start, Neighbour::Edge)];
+        ids.insert(sets[0].clone(), 0);
+        let mut transitions = HashMap::new();
+
+        let mut next_id = 0;
+        while next_id < sets.len() {
+            let from = next_id as u32;
+            let states = sets[next_id].clone();
+            next_id += 1;
+
+            let otherwise = move_states(&states, otherwise_char);
+            let mut add_set = |set: (BTreeSet<u32>, Neighbour)| {
+                *ids.entry(set.clone()).or_insert_with(|| {
+                    sets.push(set);
+                    sets.len() as u32 - 1
+                })
+            };
+
+            if !otherwise.0.is_empty() {
+                transitions.insert((from, None), add_set(otherwise.clone()));
+            }
+            for (id, class) in classes.iter().enumerate() {
+                // every character in a class goes the same way as its first
+                let next_states = move_states(&states, class[0].0);
+                // classes that go the same way as everything else
+                // are covered by the `None` transition
+                if next_states == otherwise {
+                    continue;
+                }
+                // moving to the empty set is a dead end that can't be left
+                transitions.insert((from, Some(id as u32)), add_set(next_states));
+            }
+        }
+
+        let accepting_states = ids
+            .iter()
+            .filter(|((states, before), _)| {
+                let around = Surroundings {
+                    before: *before,
+                    after: Neighbour::Edge,
+                };
+                self.close_states(states, around)
+                    .iter()
+                    .any(|s| self.accepting_states.contains(s))
+            })
+            .map(|(_, id)| *id)
+            .collect();
+
+        Dfa::from_classes(transitions, ranges, accepting_states)
+    }
+
+    /// Returns false if the NFA can be shown to accept no input at all, as
+    /// when its assertions can never hold where they are, like the `\b` in
+    /// `a\bb`.
+    ///
+    /// Follows every path from the start, keeping track of what was read
+    /// last so that assertions can be checked. It may miss some paths that
+    /// can never be taken, so returning true doesn't mean a match is possible.
+    pub fn can_match(&self) -> bool {
+        let mut reading: HashMap<u32, Vec<(&NFAChar, &Vec<u32>)>> = HashMap::new();
+        for ((from, input), to) in &self.transitions {
+            reading.entry(*from).or_default().push((input, to));
+        }
+
+        let mut seen = HashSet::new();
+        let mut stack = vec![(0, Neighbour::Edge)];
+        while let Some((state, before)) = stack.pop() {
+            if !seen.insert((state, before)) {
+                continue;
+            }
+            let mut states = BTreeSet::new();
+            states.insert(state);
+            let at_end = Surroundings {
+                before,
+                after: Neighbour::Edge,
+            };
+            if self
+                .close_states(&states, at_end)
+                .iter()
+                .any(|s| self.accepting_states.contains(s))
+            {
+                return true;
+            }
+
+            for after in [Neighbour::Newline, Neighbour::Word, Neighbour::Other] {
+                let around = Surroundings { before, after };
+                for from in self.close_states(&states, around) {
+                    for (input, targets) in reading.get(&from).into_iter().flatten() {
+                        if input.may_read(after) {
+                            stack.extend(targets.iter().map(|to| (*to, after)));
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+/// Runs an NFA on input given to it a character at a time,
+/// created by `Nfa::matcher`.
+pub struct Matcher<'a> {
+    nfa: &'a Nfa,
+    /// The states the last character led to. Transitions that read nothing
+    /// aren't followed until the next character is known, since assertions
+    /// depend on it. Kept in order so that following them doesn't depend
+    /// on how a hash set happens to be laid out.
+    states: BTreeSet<u32>,
+    /// The last character read, if any.
+    last: Option<char>,
+}
+
+impl<'a> Matcher<'a> {
+    /// The states reached so far, followed as if `next` came next.
+    fn closed_states(&self, next: Option<char>) -> BTreeSet<u32> {
+        let around = Surroundings::new(self.last, next);
+        let mut closed = self.states.clone();
+        for state in &self.states {
+            self.nfa
+                .follow_epilon_transition(&mut closed, *state, around);
+        }
+        closed
+    }
+
+    /// Read the next character.
+    pub fn push(&mut self, c: char) {
+        self.step(c);
+    }
+
+    /// Read the next character, returning how many states were stepped.
+    fn step(&mut self, c: char) -> usize {
+        let closed = self.closed_states(Some(c));
+        let mut next_states = BTreeSet::new();
+        // follow every path at once: each current state moves on its own
+        for state in &closed {
+            next_states.extend(self.nfa.step(*state, c));
+        }
+        self.states = next_states;
+        self.last = Some(c);
+        closed.len()
+    }
+
+    /// Returns true if the NFA would accept if the input ended here.
+    pub fn is_accepting(&self) -> bool {
+        self.accepts_before(None)
+    }
+
+    /// Returns true if the NFA accepts what has been read so far,
+    /// given that `next` comes after it.
+    fn accepts_before(&self, next: Option<char>) -> bool {
+        self.closed_states(next)
+            .iter()
+            .any(|s| self.nfa.accepting_states.contains(s))
+    }
+
+    /// Returns true if the NFA accepts however the input goes on, because it
+    /// is in an accepting state that reads any character back into itself,
+    /// like the one after an expression that doesn't end with `$`.
+    pub fn will_accept(&self) -> bool {
+        self.states.iter().any(|state| {
+            self.nfa.accepting_states.contains(state)
+                && self
+                    .nfa
+                    .transitions
+                    .get(&(*state, NFAChar::Any))
+                    .is_some_and(|targets| targets.contains(state))
+        })
+    }
+
+    /// Returns true if there are no paths left to follow,
+    /// so no more input can make the NFA accept.
+    pub fn is_dead(&self) -> bool {
+        self.states.is_empty()
+    }
 }
 
-impl Nfa {
-    /// Create a new NFA
-    ///
-    /// Give a transition function in the form of a `Transition`.
-    /// Use `None` as the input character to add transition on everything after
-    /// the `Some(x)` transitions.
-    ///
-    /// Give the set of accepting states in the form of a `HashSet`.
-    /// Refer to states by an integer.
-    pub fn new(transitions: Transition, accepting_states: HashSet<u32>) -> Nfa {
-        Nfa {
-            transitions,
-            accepting_states,
-        }
+impl Automaton for Nfa {
+    fn step(&self, state: u32, c: char) -> Vec<u32> {
+        Nfa::step(self, state, c)
     }
 
-    /// Recursively find all states connected through epsilon transitions
-    /// to given state: curr.
-    fn follow_epilon_transition(&self, current_states: &mut HashSet<u32>, curr: u32) {
-        let state_transition = (curr, NFAChar::Epsilon);
-        if let Some(new_state) = self.transitions.get(&state_transition) {
-            current_states.insert(*new_state);
-            self.follow_epilon_transition(current_states, *new_state);
-        };
+    fn epsilon_closure(&self, state: u32, before: Option<char>, after: Option<char>) -> Vec<u32> {
+        let mut closed = BTreeSet::new();
+        closed.insert(state);
+        self.follow_epilon_transition(&mut closed, state, Surroundings::new(before, after));
+        closed.into_iter().collect()
     }
 
-    /// Run the NFA
-    ///
-    /// give a unicode string as an input.
-    /// return true if the NFA accepts.
-    /// return false if the NFA rejects.
-    pub fn run(&self, input: String) -> bool {
-        let mut current_states = HashSet::new();
-        let mut next_states = HashSet::new();
-        current_states.insert(0);
-        self.follow_epilon_transition(&mut current_states, 0);
+    fn is_accepting(&self, state: u32) -> bool {
+        self.accepting_states.contains(&state)
+    }
+}
 
-        for c in input.chars() {
-            let mut successes = 0;
-            let mut check_else = true;
-
-            // TODO: use multithreading
-            for state in &current_states {
-                let state_transition = (*state, NFAChar::If(c));
-                if let Some(new_state) = self.transitions.get(&state_transition) {
-                    successes += 1;
-                    next_states.insert(*new_state);
-                    self.follow_epilon_transition(&mut next_states, *new_state);
-                    check_else = false;
-                };
+/// Two NFAs are equal if they have the same transitions, with their targets
+/// in the same order, and the same accepting states.
+impl PartialEq for Nfa {
+    fn eq(&self, other: &Nfa) -> bool {
+        // everything else is worked out from these in `new`
+        self.transitions == other.transitions && self.accepting_states == other.accepting_states
+    }
+}
 
-                if check_else {
-                    let state_transition = (*state, NFAChar::Else);
-                    if let Some(new_state) = self.transitions.get(&state_transition) {
-                        successes += 1;
-                        next_states.insert(*new_state);
-                        self.follow_epilon_transition(&mut next_states, *new_state);
-                    };
-                }
-            }
+impl Eq for Nfa {}
 
-            // exit if there were no valid transitions
-            if successes == 0 {
-                return false;
-            }
+impl Hash for Nfa {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // the maps have no order of their own, so combine the hash of each
+        // entry in a way that doesn't depend on the order they come in
+        let mut transitions: u64 = 0;
+        for entry in &self.transitions {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            entry.hash(&mut hasher);
+            transitions = transitions.wrapping_add(hasher.finish());
+        }
+        transitions.hash(state);
 
-            // move the next states into the current states
-            // TODO: use pointers instead of copying to reduce time complexity
-            current_states = HashSet::new();
-            for state in &next_states {
-                current_states.insert(*state);
-            }
+        let mut accepting_states: Vec<u32> = self.accepting_states.iter().copied().collect();
+        accepting_states.sort_unstable();
+        accepting_states.hash(state);
+    }
+}
+
+/// Lists each transition as `q0 --a--> q1`, followed by the accepting states.
+///
+/// `Else` is shown as `.` and `Any` as `any`; everything else is labelled as
+/// in `to_dot`.
+impl fmt::Debug for Nfa {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut edges: Vec<(u32, u32, String)> = self
+            .transitions
+            .iter()
+            .flat_map(|((from, input), targets)| {
+                let label = match input {
+                    NFAChar::Else => String::from("."),
+                    NFAChar::Any => String::from("any"),
+                    input => input.label(),
+                };
+                targets.iter().map(move |to| (*from, *to, label.clone()))
+            })
+            .collect();
+        edges.sort();
+        let mut accepting_states: Vec<u32> = self.accepting_states.iter().copied().collect();
+        accepting_states.sort_unstable();
+
+        writeln!(f, "Nfa {{")?;
+        for (from, to, label) in edges {
+            writeln!(f, "    q{} --{}--> q{}", from, label, to)?;
         }
+        let accepting_states: Vec<String> = accepting_states
+            .iter()
+            .map(|state| format!("q{}", state))
+            .collect();
+        writeln!(f, "    accepting: {{{}}}", accepting_states.join(", "))?;
+        write!(f, "}}")
+    }
+}
 
-        for s in &current_states {
-            if self.accepting_states.contains(s) {
-                return true;
-            }
+/// The parts of an NFA that get serialized.
+/// Transitions are written as a list, since formats like JSON only allow
+/// strings as map keys.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct SerializedNfa {
+    transitions: Vec<(u32, NFAChar, Vec<u32>)>,
+    accepting_states: HashSet<u32>,
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Nfa {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedNfa {
+            transitions: self
+                .transitions
+                .iter()
+                .map(|((from, input), to)| (*from, input.clone(), to.clone()))
+                .collect(),
+            accepting_states: self.accepting_states.clone(),
         }
+        .serialize(serializer)
+    }
+}
 
-        false
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Nfa {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Nfa, D::Error> {
+        let nfa = SerializedNfa::deserialize(deserializer)?;
+        let transitions = nfa
+            .transitions
+            .into_iter()
+            .map(|(from, input, to)| ((from, input), to))
+            .collect();
+        Ok(Nfa::new(transitions, nfa.accepting_states))
     }
 }
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -121,15 +1599,15 @@ mod tests {
         accepting_states.insert(0);
         let nfa = Nfa::new(Transition::new(), accepting_states);
 
-        assert!(nfa.run("".to_string()));
+        assert!(nfa.run(""));
 
         let mut accepting_states = HashSet::new();
         accepting_states.insert(1);
         let mut transitions = Transition::new();
-        transitions.insert((0, NFAChar::If('0')), 1);
+        transitions.insert((0, NFAChar::If('0')), vec![1]);
         let nfa = Nfa::new(transitions, accepting_states);
 
-        assert!(!nfa.run("".to_string()));
+        assert!(!nfa.run(""));
     }
 
     #[test]
@@ -137,21 +1615,21 @@ mod tests {
         let mut accepting_states = HashSet::new();
         accepting_states.insert(1);
         let mut transitions = Transition::new();
-        transitions.insert((0, NFAChar::If('0')), 1);
+        transitions.insert((0, NFAChar::If('0')), vec![1]);
         let nfa = Nfa::new(transitions, accepting_states);
 
-        assert!(nfa.run("0".to_string()));
-        assert!(!nfa.run("1".to_string()));
+        assert!(nfa.run("0"));
+        assert!(!nfa.run("1"));
 
         let mut accepting_states = HashSet::new();
         accepting_states.insert(1);
         accepting_states.insert(2);
         let mut transitions = Transition::new();
-        transitions.insert((0, NFAChar::If('0')), 1);
-        transitions.insert((0, NFAChar::If('1')), 2);
+        transitions.insert((0, NFAChar::If('0')), vec![1]);
+        transitions.insert((0, NFAChar::If('1')), vec![2]);
         let nfa = Nfa::new(transitions, accepting_states);
 
-        assert!(nfa.run("1".to_string()));
+        assert!(nfa.run("1"));
     }
 
     #[test]
@@ -159,10 +1637,10 @@ mod tests {
         let mut accepting_states = HashSet::new();
         accepting_states.insert(1);
         let mut transitions = Transition::new();
-        transitions.insert((0, NFAChar::Else), 1);
+        transitions.insert((0, NFAChar::Else), vec![1]);
         let nfa = Nfa::new(transitions, accepting_states);
 
-        assert!(nfa.run("0".to_string()));
+        assert!(nfa.run("0"));
     }
 
     #[test]
@@ -170,13 +1648,78 @@ mod tests {
         let mut accepting_states = HashSet::new();
         accepting_states.insert(1);
         let mut transitions = Transition::new();
-        transitions.insert((0, NFAChar::Else), 1);
-        transitions.insert((0, NFAChar::If('0')), 2);
+        transitions.insert((0, NFAChar::Else), vec![1]);
+        transitions.insert((0, NFAChar::If('0')), vec![2]);
+        let nfa = Nfa::new(transitions, accepting_states);
+
+        assert!(!nfa.run("0"));
+
+        assert!(nfa.run("1"));
+    }
+
+    #[test]
+    fn else_alongside_several_ifs() {
+        // `Else` is only taken on characters no `If` out of state 0 reads
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(4);
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::If('a')), vec![1]);
+        transitions.insert((0, NFAChar::If('b')), vec![2]);
+        transitions.insert((0, NFAChar::Else), vec![3]);
+        transitions.insert((1, NFAChar::If('!')), vec![4]);
+        transitions.insert((2, NFAChar::If('?')), vec![4]);
+        transitions.insert((3, NFAChar::If('.')), vec![4]);
+        let nfa = Nfa::new(transitions, accepting_states);
+
+        assert!(nfa.run("a!"));
+        assert!(nfa.run("b?"));
+        assert!(nfa.run("c."));
+        assert!(!nfa.run("a."));
+        assert!(!nfa.run("b."));
+        assert!(!nfa.run("a?"));
+        assert!(nfa.to_dfa().run("c."));
+        assert!(!nfa.to_dfa().run("a."));
+    }
+
+    #[test]
+    fn else_from_each_of_several_states() {
+        // states 1 and 2 are both current, and each one decides on its own
+        // whether its `Else` is taken
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(3);
+        accepting_states.insert(4);
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::Epsilon), vec![1, 2]);
+        transitions.insert((1, NFAChar::If('a')), vec![3]);
+        transitions.insert((2, NFAChar::Else), vec![4]);
         let nfa = Nfa::new(transitions, accepting_states);
 
-        assert!(!nfa.run("0".to_string()));
+        let mut matcher = nfa.matcher();
+        matcher.push('a');
+        assert!(matcher.is_accepting());
+        assert_eq!(nfa.find("a"), Some((0, 1)));
+        assert!(nfa.run("b"));
+        assert!(nfa.to_dfa().run("a"));
+        assert!(nfa.to_dfa().run("b"));
+        assert!(!nfa.run("ab"));
+    }
+
+    #[test]
+    fn transition_on_any_alongside_some() {
+        // following the `If` leads to a dead end, but `Any` is taken as well
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(3);
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::If('a')), vec![1]);
+        transitions.insert((0, NFAChar::Any), vec![2]);
+        transitions.insert((1, NFAChar::If('x')), vec![1]);
+        transitions.insert((2, NFAChar::If('b')), vec![3]);
+        let nfa = Nfa::new(transitions, accepting_states);
 
-        assert!(nfa.run("1".to_string()));
+        assert!(nfa.run("ab"));
+        assert!(nfa.run("zb"));
+        assert!(!nfa.run("ax"));
+        assert_eq!(nfa.find("ab"), Some((0, 2)));
     }
 
     #[test]
@@ -184,11 +1727,11 @@ mod tests {
         let mut accepting_states = HashSet::new();
         accepting_states.insert(1);
         let mut transitions = Transition::new();
-        transitions.insert((0, NFAChar::Else), 0);
-        transitions.insert((0, NFAChar::If('1')), 1);
+        transitions.insert((0, NFAChar::Else), vec![0]);
+        transitions.insert((0, NFAChar::If('1')), vec![1]);
         let nfa = Nfa::new(transitions, accepting_states);
 
-        assert!(nfa.run("0001".to_string()));
+        assert!(nfa.run("0001"));
     }
 
     #[test]
@@ -196,25 +1739,600 @@ mod tests {
         let mut accepting_states = HashSet::new();
         accepting_states.insert(1);
         let mut transitions = Transition::new();
-        transitions.insert((0, NFAChar::Epsilon), 1);
+        transitions.insert((0, NFAChar::Epsilon), vec![1]);
+        let nfa = Nfa::new(transitions, accepting_states);
+
+        assert!(nfa.run(""));
+
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(4);
+
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::If('a')), vec![1]);
+        transitions.insert((0, NFAChar::Epsilon), vec![1]);
+        transitions.insert((1, NFAChar::If('b')), vec![2]);
+        transitions.insert((1, NFAChar::Epsilon), vec![2]);
+        transitions.insert((2, NFAChar::If('c')), vec![3]);
+        transitions.insert((2, NFAChar::Epsilon), vec![3]);
+        transitions.insert((3, NFAChar::If('d')), vec![4]);
+
+        let nfa = Nfa::new(transitions, accepting_states);
+
+        assert!(nfa.run("acd"));
+    }
+
+    #[test]
+    fn follow_all_paths() {
+        // reading `a` leads state 1 to a dead end, but state 2 reads it
+        // with its `Else` transition and can still accept
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(4);
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::Epsilon), vec![1, 2]);
+        transitions.insert((1, NFAChar::If('a')), vec![3]);
+        transitions.insert((2, NFAChar::Else), vec![2]);
+        transitions.insert((2, NFAChar::If('x')), vec![4]);
         let nfa = Nfa::new(transitions, accepting_states);
 
-        assert!(nfa.run("".to_string()));
+        for _ in 0..10 {
+            assert!(nfa.run("ax"));
+            assert!(nfa.run("bbx"));
+            assert!(!nfa.run("ab"));
+        }
+    }
 
+    #[test]
+    fn multiple_epsilon_transitions() {
         let mut accepting_states = HashSet::new();
+        accepting_states.insert(2);
         accepting_states.insert(4);
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::Epsilon), vec![1, 3]);
+        transitions.insert((1, NFAChar::If('a')), vec![2]);
+        transitions.insert((3, NFAChar::If('b')), vec![4]);
+        let nfa = Nfa::new(transitions, accepting_states);
+
+        assert!(nfa.run("a"));
+        assert!(nfa.run("b"));
+        assert!(!nfa.run("c"));
+    }
+
+    #[test]
+    fn multiple_targets() {
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(3);
+        let mut transitions = Transition::new();
+        add_transition(&mut transitions, 0, NFAChar::If('a'), 1);
+        add_transition(&mut transitions, 0, NFAChar::If('a'), 2);
+        add_transition(&mut transitions, 1, NFAChar::If('b'), 3);
+        add_transition(&mut transitions, 2, NFAChar::If('c'), 3);
+        let nfa = Nfa::new(transitions, accepting_states);
+
+        assert!(nfa.run("ab"));
+        assert!(nfa.run("ac"));
+        assert!(!nfa.run("ad"));
+    }
+
+    #[test]
+    fn epsilon_cycle() {
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(2);
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::Epsilon), vec![1]);
+        transitions.insert((1, NFAChar::Epsilon), vec![0]);
+        transitions.insert((1, NFAChar::If('a')), vec![2]);
+        let nfa = Nfa::new(transitions, accepting_states);
+
+        assert!(nfa.run("a"));
+        assert!(!nfa.run("b"));
+        assert_eq!(nfa.find("a"), Some((0, 1)));
+
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::Epsilon), vec![0]);
+        let nfa = Nfa::new(transitions, HashSet::new());
+
+        assert!(!nfa.run(""));
+        assert_eq!(nfa.find(""), None);
+    }
+
+    #[test]
+    fn long_epsilon_chain() {
+        let length = 200_000;
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(length + 1);
+        let mut transitions = Transition::new();
+        for state in 0..length {
+            transitions.insert((state, NFAChar::Epsilon), vec![state + 1]);
+        }
+        transitions.insert((length, NFAChar::If('a')), vec![length + 1]);
+        let nfa = Nfa::new(transitions, accepting_states);
+
+        assert!(nfa.run("a"));
+        assert_eq!(nfa.find("a"), Some((0, 1)));
+    }
+
+    #[test]
+    fn transition_on_class() {
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(1);
+        let mut transitions = Transition::new();
+        transitions.insert(
+            (0, NFAChar::OneOf(['a', 'b'].iter().cloned().collect())),
+            vec![1],
+        );
+        let nfa = Nfa::new(transitions, accepting_states);
 
+        assert!(nfa.run("a"));
+        assert!(nfa.run("b"));
+        assert!(!nfa.run("c"));
+    }
+
+    #[test]
+    fn transition_on_predicate() {
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(1);
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::Predicate(Predicate::Digit)), vec![1]);
+        let nfa = Nfa::new(transitions, accepting_states);
+
+        assert!(nfa.run("7"));
+        assert!(!nfa.run("x"));
+    }
+
+    #[test]
+    fn introspection() {
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(3);
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::If('a')), vec![1, 2]);
+        transitions.insert((1, NFAChar::If('b')), vec![3]);
+        transitions.insert((2, NFAChar::Predicate(Predicate::Digit)), vec![3]);
+        transitions.insert((2, NFAChar::If('a')), vec![3]);
+        let nfa = Nfa::new(transitions, accepting_states);
+
+        assert_eq!(nfa.state_count(), 4);
+        assert_eq!(nfa.transition_count(), 5);
+        assert_eq!(nfa.accepting_states(), &[3].iter().copied().collect());
+        assert_eq!(nfa.alphabet(), ['a', 'b'].iter().copied().collect());
+
+        let nfa = Nfa::new(Transition::new(), HashSet::new());
+        assert_eq!(nfa.state_count(), 1);
+        assert_eq!(nfa.transition_count(), 0);
+        assert!(nfa.alphabet().is_empty());
+
+        // `^ab$` reads each character from its own state, between
+        // assertions that each take an epsilon transition to reach
+        let nfa = crate::compile("^ab$");
+        assert_eq!(nfa.state_count(), 7);
+        assert_eq!(nfa.transition_count(), 6);
+        assert_eq!(nfa.alphabet(), ['a', 'b'].iter().copied().collect());
+    }
+
+    #[test]
+    fn equal_nfas() {
+        fn hash(nfa: &Nfa) -> u64 {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            nfa.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let first = crate::compile("(a|b)*c\\b[ x-z]+");
+        let second = crate::compile("(a|b)*c\\b[ x-z]+");
+        assert!(first == second);
+        assert_eq!(hash(&first), hash(&second));
+        assert!(crate::compile("(a|b)*c") != crate::compile("(b|a)*c"));
+        assert!(crate::compile("abc") != crate::compile("^abc"));
+
+        let mut cache = HashSet::new();
+        cache.insert(first);
+        assert!(cache.contains(&second));
+        assert!(!cache.contains(&crate::compile("abc")));
+    }
+
+    #[test]
+    fn concat() {
+        // read exactly `ab` and `c+`, without the assertions that `^` and `$`
+        // would check against the whole input
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::If('a')), vec![1]);
+        transitions.insert((1, NFAChar::If('b')), vec![2]);
+        let ab = Nfa::new(transitions, [2].iter().copied().collect());
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::If('c')), vec![1]);
+        transitions.insert((1, NFAChar::If('c')), vec![1]);
+        let c_plus = Nfa::new(transitions, [1].iter().copied().collect());
+
+        let nfa = Nfa::concat(&ab, &c_plus);
+        for (input, accepted) in &[
+            ("abc", true),
+            ("abccc", true),
+            ("ab", false),
+            ("c", false),
+            ("abcx", false),
+            ("acb", false),
+        ] {
+            assert_eq!(nfa.run(input), *accepted, "{:?}", input);
+        }
+        // the loops of unanchored NFAs read whatever is around and between them
+        let nfa = Nfa::concat(&crate::compile("ab"), &crate::compile("cd"));
+        assert!(nfa.run("xabycdz"));
+        assert!(nfa.run("abcd"));
+        assert!(!nfa.run("cdab"));
+        // while a `$` that ends the first only holds at the end of the input
+        assert!(!Nfa::concat(&crate::compile("^ab$"), &c_plus).run("abc"));
+        assert!(!Nfa::concat(&ab, &crate::compile("^c")).run("abc"));
+        // an NFA that accepts nothing but the empty input changes nothing
+        let empty = Nfa::new(Transition::new(), [0].iter().copied().collect());
+        let nfa = Nfa::concat(&empty, &ab);
+        assert!(nfa.run("ab"));
+        assert!(!nfa.run(""));
+
+        let nfa = Nfa::concat(&crate::compile("^(a)"), &crate::compile("(b)$"));
+        assert_eq!(
+            nfa.captures("ab"),
+            Some(vec![Some((0, 2)), Some((0, 1)), Some((1, 2))])
+        );
+        assert_eq!(
+            nfa.captures("axxb"),
+            Some(vec![Some((0, 4)), Some((0, 1)), Some((3, 4))])
+        );
+
+        // a match starts where the first part does, wherever that is
+        let nfa = Nfa::concat(&crate::compile("ab"), &crate::compile("cd"));
+        assert_eq!(nfa.find("xxabyycdzz"), Some((2, 8)));
+        assert_eq!(nfa.find_at("xxabyycdzz", 1), Some((2, 8)));
+        assert_eq!(nfa.find_at("abcdxxabyycd", 1), Some((6, 12)));
+        assert_eq!(nfa.find_at("abcdxxab", 1), None);
+    }
+
+    #[test]
+    fn union() {
+        let nfa = Nfa::union(&crate::compile("^ab$"), &crate::compile("^c+$"));
+        for (input, accepted) in &[
+            ("ab", true),
+            ("ccc", true),
+            ("", false),
+            ("abc", false),
+            ("a", false),
+        ] {
+            assert_eq!(nfa.run(input), *accepted, "{:?}", input);
+        }
+        assert_eq!(nfa.accepting_states().len(), 2);
+        assert!(nfa.to_dfa().run("cc"));
+        assert!(!nfa.to_dfa().run("abab"));
+
+        // the matches of the first are preferred, and groups are kept apart
+        let nfa = Nfa::union(&crate::compile("^(a)"), &crate::compile("^(ab)"));
+        assert_eq!(nfa.find("ab"), Some((0, 1)));
+        assert_eq!(
+            nfa.captures("ab"),
+            Some(vec![Some((0, 1)), Some((0, 1)), None])
+        );
+        assert_eq!(nfa.find_longest("ab"), Some((0, 2)));
+
+        // either can start anywhere, not only at the start of the input
+        let nfa = Nfa::union(&crate::compile("ab"), &crate::compile("cd"));
+        assert_eq!(nfa.find("xxab"), Some((2, 4)));
+        assert_eq!(nfa.find("xxcd"), Some((2, 4)));
+        assert_eq!(nfa.find("xcdab"), Some((1, 3)));
+        assert_eq!(nfa.find_at("abxcd", 1), Some((3, 5)));
+        assert_eq!(nfa.find_at("abxab", 1), Some((3, 5)));
+        assert_eq!(nfa.find_longest_at("xxcd", 1), Some((2, 4)));
+        assert_eq!(nfa.find_at("abx", 1), None);
+    }
+
+    #[test]
+    fn can_match() {
+        // the accepting state can't be reached
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(2);
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::If('a')), vec![1]);
+        transitions.insert((1, NFAChar::Assert(AssertKind::StartText)), vec![2]);
+        assert!(!Nfa::new(transitions.clone(), accepting_states.clone()).can_match());
+
+        transitions.insert((1, NFAChar::Epsilon), vec![2]);
+        assert!(Nfa::new(transitions, accepting_states).can_match());
+        assert!(!Nfa::new(Transition::new(), [1].iter().copied().collect()).can_match());
+        assert!(Nfa::new(Transition::new(), [0].iter().copied().collect()).can_match());
+    }
+
+    #[test]
+    fn cloned_nfa_matches_the_same() {
+        let nfa = crate::compile("(a|b)*c\\b[ x-z]+");
+        let clone = nfa.clone();
+        assert!(clone == nfa);
+        for input in &["abc x", "c", "bbcz", "", "ac yy", "acx"] {
+            assert_eq!(clone.run(input), nfa.run(input), "{:?}", input);
+            assert_eq!(clone.find(input), nfa.find(input), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn find_after_loop_at_start() {
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(2);
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::Else), vec![0]);
+        transitions.insert((0, NFAChar::If('1')), vec![1]);
+        transitions.insert((1, NFAChar::If('2')), vec![2]);
+        let nfa = Nfa::new(transitions, accepting_states);
+
+        assert_eq!(nfa.find("0012"), Some((2, 4)));
+        assert_eq!(nfa.find("0013"), None);
+    }
+
+    #[test]
+    fn run_outcome() {
+        let nfa = crate::compile("^héllo$");
+        assert_eq!(nfa.run_outcome("héllo"), MatchOutcome::Accepted);
+        assert_eq!(
+            nfa.run_outcome("héllp"),
+            MatchOutcome::RejectedNoTransition { at: 5 }
+        );
+        assert_eq!(
+            nfa.run_outcome("héllo!"),
+            MatchOutcome::RejectedNoTransition { at: 6 }
+        );
+        assert_eq!(nfa.run_outcome("hél"), MatchOutcome::RejectedNotAccepting);
+        // an unanchored NFA can always read on, looking for a later match
+        let nfa = crate::compile("ab");
+        assert_eq!(nfa.run_outcome("xxab!"), MatchOutcome::Accepted);
+        assert_eq!(nfa.run_outcome("xxa"), MatchOutcome::RejectedNotAccepting);
+        assert!(!nfa.run("xxa"));
+    }
+
+    #[test]
+    fn run_with_budget() {
+        let nfa = crate::compile("^a*b$");
+        assert_eq!(nfa.run_with_budget("aaab", 100), Ok(true));
+        assert_eq!(nfa.run_with_budget("aaac", 100), Ok(false));
+        // each character steps at least one state
+        assert_eq!(nfa.run_with_budget("aaab", 3), Err(BudgetExceeded));
+        assert_eq!(nfa.run_with_budget("", 0), Ok(false));
+    }
+
+    #[test]
+    fn find_longest() {
+        let nfa = crate::compile("a|ab");
+        assert_eq!(nfa.find("ab"), Some((0, 1)));
+        assert_eq!(nfa.find_longest("ab"), Some((0, 2)));
+        // the match that starts earliest still wins over a longer one
+        let nfa = crate::compile("b|abc");
+        assert_eq!(nfa.find_longest("xbabc"), Some((1, 2)));
+        assert_eq!(nfa.find_longest_at("xbabc", 2), Some((2, 5)));
+
+        // state 1 accepts but leads on to a longer match through state 2
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(1);
+        accepting_states.insert(3);
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::If('a')), vec![1]);
+        transitions.insert((1, NFAChar::If('b')), vec![2]);
+        transitions.insert((2, NFAChar::If('c')), vec![3]);
+        let nfa = Nfa::new(transitions, accepting_states);
+        assert_eq!(nfa.find_longest("abc"), Some((0, 3)));
+        assert_eq!(nfa.find_longest("abx"), Some((0, 1)));
+        assert_eq!(nfa.find_anchored_longest_at("abc", 0), Some(3));
+    }
+
+    #[test]
+    fn find_prefers_the_same_class_every_time() {
+        // both classes read `a`, but only the one to state 1 accepts straight away
+        for _ in 0..20 {
+            let mut accepting_states = HashSet::new();
+            accepting_states.insert(1);
+            accepting_states.insert(3);
+            let mut transitions = Transition::new();
+            transitions.insert(
+                (0, NFAChar::OneOf(['a'].iter().cloned().collect())),
+                vec![1],
+            );
+            transitions.insert((0, NFAChar::Predicate(Predicate::Word)), vec![2]);
+            transitions.insert((2, NFAChar::If('a')), vec![3]);
+            transitions.insert((1, NFAChar::Any), vec![1]);
+            let nfa = Nfa::new(transitions, accepting_states);
+
+            assert_eq!(nfa.find("aa"), Some((0, 1)));
+        }
+    }
+
+    #[test]
+    fn run_on_iterator() {
+        let nfa = crate::compile("^(ab)+\\b");
+        assert!(nfa.run_iter("ab".chars().cycle().take(1000)));
+        assert!(!nfa.run_iter("ab".chars().cycle().take(1001)));
+        // stops reading once nothing can match
+        assert!(!nfa.run_iter("b".chars().chain(std::iter::repeat('a'))));
+        // or once it has matched and isn't anchored to the end
+        let nfa = crate::compile("ab");
+        assert!(nfa.run_iter("xab".chars().chain(std::iter::repeat('x'))));
+        let nfa = crate::compile("ab$");
+        assert!(!nfa.run_iter("xab".chars().chain("x".chars())));
+        assert!(nfa.run_iter("xab".chars()));
+    }
+
+    #[test]
+    fn matcher() {
+        let nfa = crate::compile("^a+\\b");
+        let mut matcher = nfa.matcher();
+        assert!(!matcher.is_accepting());
+        matcher.push('a');
+        assert!(matcher.is_accepting());
+        matcher.push('a');
+        assert!(matcher.is_accepting());
+        assert!(!matcher.will_accept());
+        matcher.push(' ');
+        assert!(matcher.is_accepting());
+        assert!(matcher.will_accept());
+        assert!(!matcher.is_dead());
+
+        let mut matcher = nfa.matcher();
+        matcher.push('b');
+        assert!(matcher.is_dead());
+        assert!(!matcher.is_accepting());
+    }
+
+    #[test]
+    fn run_on_bytes() {
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(2);
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::If('a')), vec![1]);
+        transitions.insert((1, NFAChar::If('\u{ff}')), vec![2]);
+        let nfa = Nfa::new(transitions, accepting_states);
+
+        assert!(nfa.run_bytes(b"a\xff"));
+        assert!(!nfa.run_bytes(b"a\xfe"));
+        assert!(nfa.run_bytes(&[b'a', 0xff]));
+        assert!(!nfa.run_bytes(b""));
+    }
+
+    #[test]
+    fn transition_on_assertion() {
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(2);
         let mut transitions = Transition::new();
-        transitions.insert((0, NFAChar::If('a')), 1);
-        transitions.insert((0, NFAChar::Epsilon), 1);
-        transitions.insert((1, NFAChar::If('b')), 2);
-        transitions.insert((1, NFAChar::Epsilon), 2);
-        transitions.insert((2, NFAChar::If('c')), 3);
-        transitions.insert((2, NFAChar::Epsilon), 3);
-        transitions.insert((3, NFAChar::If('d')), 4);
+        transitions.insert((0, NFAChar::If('a')), vec![1]);
+        transitions.insert((1, NFAChar::Assert(AssertKind::WordBoundary)), vec![2]);
+        transitions.insert((2, NFAChar::Any), vec![2]);
+        let nfa = Nfa::new(transitions, accepting_states);
+
+        assert!(nfa.run("a"));
+        assert!(nfa.run("a b"));
+        assert!(!nfa.run("ab"));
+        assert_eq!(nfa.find("a-"), Some((0, 1)));
+        assert_eq!(nfa.find("ab"), None);
+        assert!(nfa.to_dfa().run("a b"));
+        assert!(!nfa.to_dfa().run("ab"));
+    }
+
+    #[test]
+    fn to_dot() {
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(3);
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::Any), vec![0]);
+        transitions.insert((0, NFAChar::Epsilon), vec![1]);
+        transitions.insert(
+            (
+                1,
+                NFAChar::OneOf(('a'..='c').chain(vec!['x', 'y']).collect()),
+            ),
+            vec![2],
+        );
+        transitions.insert((2, NFAChar::If('\n')), vec![3]);
+        transitions.insert((2, NFAChar::Predicate(Predicate::Digit)), vec![3]);
+        transitions.insert((2, NFAChar::Else), vec![2]);
+        let nfa = Nfa::new(transitions, accepting_states);
+
+        let expected = r#"digraph {
+    rankdir=LR;
+    0 [shape=circle];
+    1 [shape=circle];
+    2 [shape=circle];
+    3 [shape=doublecircle];
+    0 -> 0 [label="."];
+    0 -> 1 [label="ε"];
+    1 -> 2 [label="[a-cxy]"];
+    2 -> 2 [label="else"];
+    2 -> 3 [label="\\d"];
+    2 -> 3 [label="\\n"];
+}
+"#;
+        assert_eq!(nfa.to_dot(), expected);
+    }
+
+    #[test]
+    fn debug() {
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(3);
+        accepting_states.insert(2);
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::Any), vec![0]);
+        transitions.insert((0, NFAChar::Epsilon), vec![1]);
+        transitions.insert((1, NFAChar::OneOf(('a'..='c').collect())), vec![2]);
+        transitions.insert((2, NFAChar::If('\n')), vec![3]);
+        transitions.insert((2, NFAChar::Else), vec![2]);
+        transitions.insert(
+            (2, NFAChar::NoneOf(['x', 'y'].iter().cloned().collect())),
+            vec![1],
+        );
+        let nfa = Nfa::new(transitions, accepting_states);
+
+        let expected = r#"Nfa {
+    q0 --any--> q0
+    q0 --ε--> q1
+    q1 --[a-c]--> q2
+    q2 --[^xy]--> q1
+    q2 --.--> q2
+    q2 --\n--> q3
+    accepting: {q2, q3}
+}"#;
+        assert_eq!(format!("{:?}", nfa), expected);
+        assert_eq!(
+            format!("{:?}", NFAChar::Predicate(Predicate::Digit)),
+            "Predicate(Digit)"
+        );
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn run_parallel() {
+        // enough states open at once to be shared out between threads
+        let nfa = crate::compile("^(a|b)*a(a|b){100}$");
+        let mut input = "ab".repeat(200);
+        assert_eq!(nfa.run_parallel(&input), nfa.run(&input));
+        for _ in 0..3 {
+            input.push('a');
+            assert_eq!(nfa.run_parallel(&input), nfa.run(&input));
+        }
+        assert!(!nfa.run_parallel("c"));
+    }
 
+    #[test]
+    fn convert_to_dfa() {
+        // `Else` only reads what the `If` doesn't
+        let mut accepting_states = HashSet::new();
+        accepting_states.insert(1);
+        let mut transitions = Transition::new();
+        transitions.insert((0, NFAChar::Else), vec![1]);
+        transitions.insert((0, NFAChar::If('0')), vec![2]);
+        transitions.insert((2, NFAChar::Epsilon), vec![3]);
+        transitions.insert((3, NFAChar::Predicate(Predicate::Digit)), vec![1]);
         let nfa = Nfa::new(transitions, accepting_states);
+        let dfa = nfa.to_dfa();
+
+        for input in &["", "0", "1", "a", "00", "01", "0a", "11", "0\u{0663}"] {
+            assert_eq!(dfa.run(input), nfa.run(input), "input {:?}", input);
+        }
+    }
+
+    #[test]
+    fn dfa_classes() {
+        // a predicate and its negation split the characters the same way
+        let classes = crate::compile("\\s\\S").dfa_classes();
+        assert_eq!(classes.len(), 2);
+        let spaces = classes.iter().find(|class| class.contains(&(' ', ' ')));
+        assert!(spaces
+            .unwrap()
+            .iter()
+            .any(|(low, high)| (*low..=*high).contains(&'\u{3000}')));
+        assert_eq!(crate::compile("\\S").dfa_classes(), classes);
 
-        assert!(nfa.run("acd".to_string()));
+        // every character is in exactly one class
+        assert_eq!(
+            crate::compile("[a-c]x").dfa_classes(),
+            vec![
+                vec![
+                    ('\0', '`'),
+                    ('d', 'w'),
+                    ('y', '\u{D7FF}'),
+                    ('\u{E000}', char::MAX)
+                ],
+                vec![('a', 'c')],
+                vec![('x', 'x')],
+            ]
+        );
     }
 }
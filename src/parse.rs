@@ -0,0 +1,874 @@
+use crate::category::Category;
+use crate::nfa::AssertKind;
+use crate::nfa::Predicate;
+use crate::RegexError;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+/// Characters with a special meaning, which have to be escaped with `\`
+/// to be matched literally.
+pub const METACHARACTERS: &[char] = &[
+    '\\', '.', '^', '$', '|', '(', ')', '[', ']', '{', '}', '?', '*', '+',
+];
+
+/// Syntax tree of a regular expression.
+pub enum Node {
+    /// Matches without reading anything.
+    Empty,
+    /// A single literal character.
+    Char(char),
+    /// Any one of a set of characters, written `[...]`.
+    Class(BTreeSet<char>),
+    /// Any character outside of a set, written `[^...]`.
+    NotClass(BTreeSet<char>),
+    /// Any character accepted by the predicate, written as a shorthand like `\d`.
+    Predicate(Predicate),
+    /// Any character at all, written `.` in dotall mode.
+    Wildcard,
+    /// A position where the assertion holds, written as an escape like `\b`.
+    Assert(AssertKind),
+    /// Each node in turn.
+    Concat(Vec<Node>),
+    /// Any one of the nodes, written `a|b`.
+    Alternation(Vec<Node>),
+    /// A node treated as a single unit, written `(...)`.
+    /// What it matches is captured as group `index`, counting from 1,
+    /// unless it is written `(?:...)`.
+    Group {
+        node: Box<Node>,
+        index: Option<usize>,
+    },
+    /// Zero or one of the node, written `?`.
+    ZeroOrOne(Box<Node>),
+    /// Zero or more of the node, written `*`.
+    ZeroOrMore(Box<Node>),
+    /// One or more of the node, written `+`.
+    OneOrMore(Box<Node>),
+    /// Between `min` and `max` of the node, written `{min,max}`.
+    /// No `max` means there is no upper limit, written `{min,}`.
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+    },
+}
+
+impl Node {
+    /// Replace every node that doesn't hold other nodes with `f` of it.
+    fn map_leaves(self, f: &impl Fn(Node) -> Node) -> Node {
+        let map = |node: Box<Node>| Box::new(node.map_leaves(f));
+        match self {
+            Node::Concat(nodes) => {
+                Node::Concat(nodes.into_iter().map(|node| node.map_leaves(f)).collect())
+            }
+            Node::Alternation(branches) => Node::Alternation(
+                branches
+                    .into_iter()
+                    .map(|branch| branch.map_leaves(f))
+                    .collect(),
+            ),
+            Node::Group { node, index } => Node::Group {
+                node: map(node),
+                index,
+            },
+            Node::ZeroOrOne(node) => Node::ZeroOrOne(map(node)),
+            Node::ZeroOrMore(node) => Node::ZeroOrMore(map(node)),
+            Node::OneOrMore(node) => Node::OneOrMore(map(node)),
+            Node::Repeat { node, min, max } => Node::Repeat {
+                node: map(node),
+                min,
+                max,
+            },
+            node => f(node),
+        }
+    }
+
+    /// Make every character in the node match regardless of case.
+    pub fn fold_case(self) -> Node {
+        self.map_leaves(&|node| match node {
+            Node::Char(c) => {
+                let variants = case_variants(c);
+                if variants.len() == 1 {
+                    Node::Char(c)
+                } else {
+                    Node::Class(variants)
+                }
+            }
+            Node::Class(members) => {
+                Node::Class(members.into_iter().flat_map(case_variants).collect())
+            }
+            Node::NotClass(members) => {
+                Node::NotClass(members.into_iter().flat_map(case_variants).collect())
+            }
+            node => node,
+        })
+    }
+
+    /// How many single-character nodes the node expands to once its counted
+    /// repetitions are written out, which is roughly how many states it
+    /// compiles to.
+    fn expanded_size(&self) -> usize {
+        match self {
+            Node::Concat(nodes) | Node::Alternation(nodes) => nodes
+                .iter()
+                .fold(0, |size, node| size.saturating_add(node.expanded_size())),
+            Node::Group { node, .. }
+            | Node::ZeroOrOne(node)
+            | Node::ZeroOrMore(node)
+            | Node::OneOrMore(node) => node.expanded_size(),
+            Node::Repeat { node, min, max } => {
+                // `{min,}` is written out as `min` copies and a `*`
+                let copies = max.unwrap_or_else(|| min.saturating_add(1));
+                node.expanded_size().saturating_mul(copies as usize)
+            }
+            _ => 1,
+        }
+    }
+
+    /// The literal characters every match of the node starts with.
+    pub fn literal_prefix(&self) -> String {
+        let mut prefix = String::new();
+        self.push_literal_prefix(&mut prefix);
+        prefix
+    }
+
+    /// Add the literal characters the node starts with to `prefix`,
+    /// returning true if that is the whole of the node.
+    fn push_literal_prefix(&self, prefix: &mut String) -> bool {
+        match self {
+            Node::Empty => true,
+            Node::Char(c) => {
+                prefix.push(*c);
+                true
+            }
+            Node::Concat(nodes) => nodes.iter().all(|node| node.push_literal_prefix(prefix)),
+            _ => false,
+        }
+    }
+
+    /// Wrap `node` in the quantifier written `c`, which is `?`, `*` or `+`.
+    ///
+    /// A quantifier on a quantifier is the same as a single one, so one that
+    /// is already quantified is given a single quantifier in place of both:
+    /// `a??` is `a?`, `a++` is `a+`, and any other pair like `a**` or `a+?`
+    /// is `a*`.
+    fn quantify(c: char, node: Node) -> Node {
+        let (previous, node) = match node {
+            Node::ZeroOrOne(node) => ('?', node),
+            Node::ZeroOrMore(node) => ('*', node),
+            Node::OneOrMore(node) => ('+', node),
+            node => (c, Box::new(node)),
+        };
+        match (previous, c) {
+            ('?', '?') => Node::ZeroOrOne(node),
+            ('+', '+') => Node::OneOrMore(node),
+            _ => Node::ZeroOrMore(node),
+        }
+    }
+
+    /// Make every `.` in the node match newlines too.
+    pub fn dotall(self) -> Node {
+        self.map_leaves(&|node| match node {
+            Node::Predicate(Predicate::NotNewline) => Node::Wildcard,
+            node => node,
+        })
+    }
+
+    /// Make `.` read a whole user-perceived character: a character along with
+    /// the combining marks after it, as `é` can be written `e\u{301}`.
+    pub fn graphemes(self) -> Node {
+        self.map_leaves(&|node| match node {
+            dot @ Node::Predicate(Predicate::NotNewline) | dot @ Node::Wildcard => {
+                let marks = Node::Predicate(Predicate::Category(Category::Mark));
+                Node::Concat(vec![dot, Node::ZeroOrMore(Box::new(marks))])
+            }
+            node => node,
+        })
+    }
+}
+
+/// Find `c` along with its other cases.
+/// Cases that are written with more than one character, like the uppercase
+/// of `ß`, can't be matched as a single character and are left out.
+fn case_variants(c: char) -> BTreeSet<char> {
+    fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    }
+
+    let mut variants = BTreeSet::new();
+    variants.insert(c);
+    let lower = single(c.to_lowercase());
+    let upper = single(c.to_uppercase());
+    // a character like the Kelvin sign only shares its lowercase with `K`
+    for variant in [lower, upper, lower.and_then(|c| single(c.to_uppercase()))] {
+        variants.extend(variant);
+    }
+    variants
+}
+
+/// The nodes read one after another to match `node`.
+fn into_sequence(node: Node) -> Vec<Node> {
+    match node {
+        Node::Empty => Vec::new(),
+        Node::Concat(nodes) => nodes,
+        node => vec![node],
+    }
+}
+
+/// The node that reads each of `nodes` in turn.
+fn from_sequence(mut nodes: Vec<Node>) -> Node {
+    match nodes.len() {
+        0 => Node::Empty,
+        1 => nodes.remove(0),
+        _ => Node::Concat(nodes),
+    }
+}
+
+/// Merge the branches of an alternation that start with the same literal
+/// characters, so that they share the states reading them, as in a trie.
+/// `abcd|abce` becomes `abc(?:d|e)`.
+///
+/// Branches starting with different characters can't match at the same
+/// position, so a branch is only moved past others that start with a
+/// character; this keeps the order branches are preferred in.
+fn factor_prefixes(branches: Vec<Node>) -> Vec<Node> {
+    let mut merged: Vec<(Option<char>, Vec<Vec<Node>>)> = Vec::new();
+    for branch in branches {
+        let sequence = into_sequence(branch);
+        let first = match sequence.first() {
+            Some(Node::Char(c)) => Some(*c),
+            _ => None,
+        };
+        let earlier = first.and_then(|c| {
+            merged
+                .iter_mut()
+                .rev()
+                .take_while(|(first, _)| first.is_some())
+                .find(|(first, _)| *first == Some(c))
+        });
+        match earlier {
+            Some((_, sequences)) => sequences.push(sequence),
+            None => merged.push((first, vec![sequence])),
+        }
+    }
+
+    let whole = merged.len() == 1;
+    merged
+        .into_iter()
+        .flat_map(|(_, sequences)| {
+            let prefix = common_prefix(&sequences);
+            // the alternation left after the prefix needs two more states
+            // than the branches saved, unless it replaces this one
+            let saved = (sequences.len() - 1) * prefix;
+            if sequences.len() == 1 || (saved <= 2 && !whole) {
+                return sequences.into_iter().map(from_sequence).collect();
+            }
+
+            let mut sequences = sequences.into_iter();
+            let mut nodes: Vec<Node> = sequences.next().unwrap();
+            let mut rests = vec![from_sequence(nodes.split_off(prefix))];
+            rests.extend(
+                sequences
+                    .map(|sequence| from_sequence(sequence.into_iter().skip(prefix).collect())),
+            );
+            let mut rests = factor_prefixes(rests);
+            if rests.len() == 1 {
+                nodes.extend(into_sequence(rests.remove(0)));
+            } else {
+                nodes.push(Node::Alternation(rests));
+            }
+            vec![from_sequence(nodes)]
+        })
+        .collect()
+}
+
+/// How many literal characters every one of `sequences` starts with in common.
+fn common_prefix(sequences: &[Vec<Node>]) -> usize {
+    let first = &sequences[0];
+    let mut length = 0;
+    while let Some(Node::Char(c)) = first.get(length) {
+        let shared = sequences[1..]
+            .iter()
+            .all(|sequence| matches!(sequence.get(length), Some(Node::Char(d)) if d == c));
+        if !shared {
+            break;
+        }
+        length += 1;
+    }
+    length
+}
+
+/// The node that matches any one of `branches`, preferring them in order.
+fn alternation(branches: Vec<Node>) -> Node {
+    let mut branches = factor_prefixes(branches);
+    if branches.len() == 1 {
+        branches.remove(0)
+    } else {
+        Node::Alternation(branches)
+    }
+}
+
+/// A parsed regular expression along with its anchors.
+pub struct Expression {
+    pub node: Node,
+    /// Every branch of the expression starts with `^`, so a match can only
+    /// start at the start of the input. The `^` is still in `node`.
+    pub anchored_start: bool,
+    /// Every branch of the expression ends with `$`, so a match can only
+    /// end at the end of the input. The `$` is still in `node`.
+    pub anchored_end: bool,
+    /// The index of each group given a name with `(?P<name>...)`.
+    pub names: HashMap<String, usize>,
+}
+
+/// The flags that can be set inside an expression, as in `(?i)`, or for
+/// all of it by a `RegexBuilder`.
+#[derive(Clone, Copy, Default)]
+pub struct Flags {
+    /// `i`: match letters regardless of case.
+    pub case_insensitive: bool,
+    /// `m`: make `^` and `$` match at the start and end of every line.
+    pub multi_line: bool,
+    /// `s`: make `.` match newlines too.
+    pub dot_all: bool,
+}
+
+/// A branch of the whole expression, between the assertions its anchors
+/// stand for.
+type AnchoredBranch = (Option<AssertKind>, Node, Option<AssertKind>);
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    /// The flags set so far in the group being read.
+    flags: Flags,
+    /// How many capture groups have been opened so far.
+    groups: usize,
+    /// The index of each named group seen so far.
+    names: HashMap<String, usize>,
+    /// The largest `expanded_size` a counted repetition may have.
+    repetition_limit: usize,
+    /// Fail on an escape that means nothing, rather than reading it as the
+    /// character after the `\`.
+    strict_escapes: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.position += 1;
+        c
+    }
+
+    /// alternation := concat ('|' concat)*
+    fn parse_alternation(&mut self) -> Result<Node, RegexError> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.peek() == Some('|') {
+            self.next();
+            branches.push(self.parse_concat()?);
+        }
+
+        Ok(alternation(branches))
+    }
+
+    /// Read the branches of the whole expression, each of which can be
+    /// anchored by a leading `^` and a trailing unescaped `$`.
+    /// Returns each branch along with the assertions its anchors stand for,
+    /// which check for the start and end of a line if the `m` flag is set.
+    fn parse_branches(&mut self) -> Result<Vec<AnchoredBranch>, RegexError> {
+        let mut branches = Vec::new();
+        loop {
+            // flags set before the `^`, as in `(?m)^a`, apply to it
+            while self.at_flags() {
+                self.next();
+                self.parse_atom('(')?;
+            }
+            let anchored_start = self.peek() == Some('^');
+            if anchored_start {
+                self.next();
+            }
+            let start_anchor = self.anchor(anchored_start, AssertKind::StartLine);
+            let start = self.position;
+            let mut node = self.parse_concat()?;
+
+            // a `$` is only an anchor at the very end, and only if it is not escaped
+            let escapes = self.chars[start..self.position]
+                .iter()
+                .rev()
+                .skip(1)
+                .take_while(|c| **c == '\\')
+                .count();
+            let anchored_end =
+                self.position > start && self.chars[self.position - 1] == '$' && escapes % 2 == 0;
+            if anchored_end {
+                let mut nodes = into_sequence(node);
+                nodes.pop();
+                node = from_sequence(nodes);
+            }
+            let end_anchor = self.anchor(anchored_end, AssertKind::EndLine);
+            branches.push((start_anchor, node, end_anchor));
+
+            if self.peek() != Some('|') {
+                return Ok(branches);
+            }
+            self.next();
+        }
+    }
+
+    /// The assertion an anchor stands for if there is one: `line` if the `m`
+    /// flag is set, or else the start or end of the whole text.
+    fn anchor(&self, anchored: bool, line: AssertKind) -> Option<AssertKind> {
+        if !anchored {
+            None
+        } else if self.flags.multi_line {
+            Some(line)
+        } else if line == AssertKind::StartLine {
+            Some(AssertKind::StartText)
+        } else {
+            Some(AssertKind::EndText)
+        }
+    }
+
+    /// Returns true if the next thing to read only sets flags, as in `(?i)`.
+    fn at_flags(&self) -> bool {
+        let rest = &self.chars[self.position..];
+        let flags = rest
+            .iter()
+            .skip(2)
+            .take_while(|c| "ims-".contains(**c))
+            .count();
+        rest.starts_with(&['(', '?']) && rest.get(2 + flags) == Some(&')')
+    }
+
+    /// Read the flags of a group written `(?flags)` or `(?flags:...)`, up to
+    /// and including the `)` or `:`, along with whether it was a `:`. Letters
+    /// after a `-` turn their flags off, and there has to be at least one
+    /// letter before the end and after a `-`. The group's `(` is at `start`.
+    fn parse_flags(&mut self, start: usize) -> Result<(Flags, bool), RegexError> {
+        let mut flags = self.flags;
+        let mut on = true;
+        let mut empty = true;
+        loop {
+            match self.next() {
+                Some(')') | Some(':') if empty => return Err(RegexError::UnknownFlag(start)),
+                Some(')') => return Ok((flags, false)),
+                Some(':') => return Ok((flags, true)),
+                Some('-') if on => {
+                    on = false;
+                    empty = true;
+                    continue;
+                }
+                Some('i') => flags.case_insensitive = on,
+                Some('m') => flags.multi_line = on,
+                Some('s') => flags.dot_all = on,
+                None => return Err(RegexError::UnbalancedParenthesis(start)),
+                Some(_) => return Err(RegexError::UnknownFlag(start)),
+            }
+            empty = false;
+        }
+    }
+
+    /// Read a number made of decimal digits.
+    fn parse_number(&mut self) -> Option<u32> {
+        let mut number: Option<u32> = None;
+        while let Some(digit) = self.peek().and_then(|c| c.to_digit(10)) {
+            self.next();
+            number = Some(number.unwrap_or(0).checked_mul(10)?.checked_add(digit)?);
+        }
+        number
+    }
+
+    /// Read the bounds of a counted repetition.
+    /// The opening `{`, at `start`, has already been consumed;
+    /// reads up to and including `}`.
+    fn parse_repetition(&mut self, start: usize) -> Result<(u32, Option<u32>), RegexError> {
+        let error = RegexError::InvalidRepetition(start);
+        let min = self.parse_number().ok_or(error)?;
+        let max = match self.next() {
+            Some('}') => return Ok((min, Some(min))),
+            Some(',') => self.parse_number(),
+            _ => return Err(RegexError::InvalidRepetition(start)),
+        };
+
+        if self.next() != Some('}') {
+            return Err(RegexError::InvalidRepetition(start));
+        }
+        match max {
+            Some(max) if max < min => Err(RegexError::InvalidRepetition(start)),
+            _ => Ok((min, max)),
+        }
+    }
+
+    /// concat := (atom quantifier*)*
+    fn parse_concat(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = Vec::new();
+        // whether the last thing read only set flags
+        let mut after_flags = false;
+        while let Some(c) = self.peek() {
+            match c {
+                '|' | ')' => break,
+                '?' | '*' | '+' | '{' => {
+                    let start = self.position;
+                    self.next();
+                    // setting flags reads nothing, so there is nothing to repeat
+                    if after_flags {
+                        return Err(RegexError::NothingToRepeat(start));
+                    }
+                    let node = nodes.pop().ok_or(RegexError::NothingToRepeat(start))?;
+                    nodes.push(match c {
+                        '?' | '*' | '+' => Node::quantify(c, node),
+                        _ => {
+                            let (min, max) = self.parse_repetition(start)?;
+                            let repeat = Node::Repeat {
+                                node: Box::new(node),
+                                min,
+                                max,
+                            };
+                            if repeat.expanded_size() > self.repetition_limit {
+                                return Err(RegexError::RepetitionTooLarge(start));
+                            }
+                            repeat
+                        }
+                    });
+                }
+                _ => {
+                    self.next();
+                    let node = self.parse_atom(c)?;
+                    // only setting flags reads nothing
+                    after_flags = matches!(node, Node::Empty);
+                    if !after_flags {
+                        nodes.push(node);
+                    }
+                }
+            }
+        }
+
+        match nodes.len() {
+            0 => Ok(Node::Empty),
+            1 => Ok(nodes.remove(0)),
+            _ => Ok(Node::Concat(nodes)),
+        }
+    }
+
+    /// Parse a single token, of which `c` is the first character.
+    fn parse_atom(&mut self, c: char) -> Result<Node, RegexError> {
+        // `c` has already been read
+        let start = self.position - 1;
+        if c == '(' {
+            return self.parse_group(start);
+        }
+        let mut node = match c {
+            // wildcard, which leaves out newlines unless in dotall mode
+            '.' => Node::Predicate(Predicate::NotNewline),
+            // escape
+            '\\' => match self.next() {
+                None => return Err(RegexError::DanglingEscape(start)),
+                Some('d') => Node::Predicate(Predicate::Digit),
+                Some('D') => Node::Predicate(Predicate::NotDigit),
+                Some('w') => Node::Predicate(Predicate::Word),
+                Some('W') => Node::Predicate(Predicate::NotWord),
+                Some('s') => Node::Predicate(Predicate::Space),
+                Some('S') => Node::Predicate(Predicate::NotSpace),
+                Some('p') => Node::Predicate(Predicate::Category(self.parse_category(start)?)),
+                Some('P') => Node::Predicate(Predicate::NotCategory(self.parse_category(start)?)),
+                Some('b') => Node::Assert(AssertKind::WordBoundary),
+                Some('B') => Node::Assert(AssertKind::NotWordBoundary),
+                Some(c) => Node::Char(self.escaped_char(c, start)?),
+            },
+            // character class
+            '[' => {
+                if self.peek() == Some('^') {
+                    self.next();
+                    Node::NotClass(self.parse_class(start)?)
+                } else {
+                    Node::Class(self.parse_class(start)?)
+                }
+            }
+            // normal character
+            _ => Node::Char(c),
+        };
+
+        // the flags set so far apply to everything read under them
+        if self.flags.case_insensitive {
+            node = node.fold_case();
+        }
+        if self.flags.dot_all {
+            node = node.dotall();
+        }
+        Ok(node)
+    }
+
+    /// The character an escape that reads a single character stands for,
+    /// where `c` follows the escape's `\` at `start`.
+    fn escaped_char(&mut self, c: char, start: usize) -> Result<char, RegexError> {
+        match c {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            'x' => self.parse_hex(start),
+            // an automaton can't remember what a group matched
+            c if c.is_ascii_digit() => Err(RegexError::UnsupportedBackreference(start)),
+            c if METACHARACTERS.contains(&c) => Ok(c),
+            // as does any other punctuation, which might have been escaped
+            // to be safe
+            c if c.is_ascii_punctuation() => Ok(c),
+            _ if self.strict_escapes => Err(RegexError::InvalidEscape(start)),
+            // anything else stands for itself too, unless escapes are strict
+            c => Ok(c),
+        }
+    }
+
+    /// Read the character a `\x` escape at `start` stands for, written as
+    /// two hex digits or as one or more in braces, as in `\x41` or `\x{1F600}`.
+    fn parse_hex(&mut self, start: usize) -> Result<char, RegexError> {
+        let braced = self.peek() == Some('{');
+        if braced {
+            self.next();
+        }
+        let mut digits = String::new();
+        while let Some(digit) = self.peek().filter(char::is_ascii_hexdigit) {
+            if !braced && digits.len() == 2 {
+                break;
+            }
+            self.next();
+            digits.push(digit);
+        }
+        if (braced && self.next() != Some('}')) || (!braced && digits.len() != 2) {
+            return Err(RegexError::InvalidEscape(start));
+        }
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or(RegexError::InvalidEscape(start))
+    }
+
+    /// Parse a group, whose `(` at `start` has already been read.
+    ///
+    /// A group that only sets flags, as in `(?i)`, sets them for the rest of
+    /// the group it is in and reads as `Empty`. One written `(?i:...)` sets
+    /// them only inside itself.
+    fn parse_group(&mut self, start: usize) -> Result<Node, RegexError> {
+        let outer_flags = self.flags;
+        let rest = &self.chars[self.position..];
+        let index = if rest.starts_with(&['?', ':']) {
+            // only used for grouping, so it isn't counted
+            self.position += 2;
+            None
+        } else if rest.starts_with(&['?']) && !rest.starts_with(&['?', 'P', '<']) {
+            self.next();
+            let (flags, scoped) = self.parse_flags(start)?;
+            self.flags = flags;
+            if !scoped {
+                return Ok(Node::Empty);
+            }
+            None
+        } else {
+            // groups are numbered in the order they are opened
+            self.groups += 1;
+            if rest.starts_with(&['?', 'P', '<']) {
+                self.position += 3;
+                let name = self
+                    .parse_group_name()
+                    .ok_or(RegexError::InvalidGroupName(start))?;
+                if self.names.insert(name, self.groups).is_some() {
+                    return Err(RegexError::InvalidGroupName(start));
+                }
+            }
+            Some(self.groups)
+        };
+        let node = self.parse_alternation()?;
+        if self.next() != Some(')') {
+            return Err(RegexError::UnbalancedParenthesis(start));
+        }
+        // flags set inside the group end with it
+        self.flags = outer_flags;
+        Ok(Node::Group {
+            node: Box::new(node),
+            index,
+        })
+    }
+
+    /// Read the name of a Unicode general category, written either as a
+    /// single letter or as a letter in braces, after a `\p` or `\P` at `start`.
+    fn parse_category(&mut self, start: usize) -> Result<Category, RegexError> {
+        let letter = match self.next() {
+            Some('{') => {
+                let letter = self.next();
+                if self.next() != Some('}') {
+                    return Err(RegexError::UnknownCategory(start));
+                }
+                letter
+            }
+            letter => letter,
+        };
+        letter
+            .and_then(Category::from_letter)
+            .ok_or(RegexError::UnknownCategory(start))
+    }
+
+    /// Read the name of a group.
+    /// The opening `(?P<` has already been consumed; reads up to and including `>`.
+    /// A name is made of word characters and doesn't start with a digit.
+    /// Returns `None` if the name is malformed.
+    fn parse_group_name(&mut self) -> Option<String> {
+        let mut name = String::new();
+        loop {
+            match self.next() {
+                Some('>') if !name.is_empty() => return Some(name),
+                Some(c) if c.is_ascii_digit() && name.is_empty() => return None,
+                Some(c) if c.is_ascii_alphanumeric() || c == '_' => name.push(c),
+                _ => return None,
+            }
+        }
+    }
+
+    /// Read the members of a bracketed character class.
+    /// The opening `[`, at `start`, has already been consumed, along with
+    /// the `^` after it if the class is negated; reads up to and including
+    /// `]`. A `]` straight away is a member rather than the end.
+    /// A `-` between two characters adds the inclusive range between them,
+    /// anywhere else it is a literal dash. Escapes are read as they are
+    /// outside of classes, so `\]` is a member rather than the end, except
+    /// that shorthands like `\d` aren't members and are rejected.
+    fn parse_class(&mut self, start: usize) -> Result<BTreeSet<char>, RegexError> {
+        let mut members = BTreeSet::new();
+        // the last member read and where it was, if it could start a range
+        let mut previous = None;
+        // a `]` straight away is a member, unless nothing closes the class
+        // after it
+        if self.peek() == Some(']') {
+            if !self.chars[self.position + 1..].contains(&']') {
+                return Err(RegexError::EmptyCharClass(start));
+            }
+            self.next();
+            members.insert(']');
+            previous = Some((']', self.position - 1));
+        }
+        while let Some(c) = self.next() {
+            let position = self.position - 1;
+            match (c, previous, self.peek()) {
+                (']', _, _) => return Ok(members),
+                ('-', Some((low, low_position)), Some(high)) if high != ']' => {
+                    // range
+                    let high_position = self.position;
+                    self.next();
+                    let high = self.class_member(high, high_position)?;
+                    if low > high {
+                        return Err(RegexError::InvalidRange(low_position));
+                    }
+                    // building a set from sorted members at once is much faster
+                    // than inserting them one by one
+                    members.append(&mut (low..=high).collect());
+                    previous = None;
+                }
+                _ => {
+                    let c = self.class_member(c, position)?;
+                    members.insert(c);
+                    previous = Some((c, position));
+                }
+            }
+        }
+
+        // ran out of characters before the class was closed
+        Err(RegexError::UnbalancedBracket(start))
+    }
+
+    /// The member of a class that `c`, read at `position`, stands for,
+    /// reading the rest of the escape if it is a `\`.
+    fn class_member(&mut self, c: char, position: usize) -> Result<char, RegexError> {
+        if c != '\\' {
+            return Ok(c);
+        }
+        match self.next() {
+            // a class only holds single characters
+            Some('d' | 'D' | 'w' | 'W' | 's' | 'S' | 'p' | 'P' | 'b' | 'B') => {
+                Err(RegexError::InvalidEscape(position))
+            }
+            Some(c) => self.escaped_char(c, position),
+            None => Err(RegexError::DanglingEscape(position)),
+        }
+    }
+}
+
+/// Build the expression matching any one of `words`, read literally.
+/// It is preferred in the order the words are given, and matches nothing
+/// if there are none.
+pub fn literals(words: &[&str]) -> Expression {
+    let branches = words
+        .iter()
+        .map(|word| from_sequence(word.chars().map(Node::Char).collect()))
+        .collect();
+    Expression {
+        node: alternation(branches),
+        anchored_start: false,
+        anchored_end: false,
+        names: HashMap::new(),
+    }
+}
+
+/// Parse a regular expression into its syntax tree.
+///
+/// A leading `^` anchors the expression to the start of the input
+/// and a trailing unescaped `$` anchors it to the end. Alternation binds
+/// loosest, so these can also anchor a single branch, as in `^a|b$`.
+/// The expression starts out with `flags`. Flags set inline with `(?ims)`
+/// apply to what comes after them in their group, and those set with
+/// `(?ims:...)` only inside it, so `(?-i)` can turn off a flag it started with.
+/// Fails if a counted repetition would expand to more than
+/// `repetition_limit` characters, and with `strict_escapes` if a letter or
+/// other character that isn't punctuation is escaped for no reason, as in `\q`.
+pub fn parse(
+    expression: &str,
+    repetition_limit: usize,
+    strict_escapes: bool,
+    flags: Flags,
+) -> Result<Expression, RegexError> {
+    let mut parser = Parser {
+        chars: expression.chars().collect(),
+        position: 0,
+        flags,
+        groups: 0,
+        names: HashMap::new(),
+        repetition_limit,
+        strict_escapes,
+    };
+    let branches = parser.parse_branches()?;
+    // the only thing that stops the expression being read early is a `)`
+    // that doesn't close a group
+    if parser.peek().is_some() {
+        return Err(RegexError::UnbalancedParenthesis(parser.position));
+    }
+
+    // every anchor is checked by an assertion in its own branch, and those
+    // shared by every branch also anchor the whole expression
+    let anchored_start = branches
+        .iter()
+        .all(|(start, _, _)| *start == Some(AssertKind::StartText));
+    let anchored_end = branches
+        .iter()
+        .all(|(_, _, end)| *end == Some(AssertKind::EndText));
+    let branches = branches
+        .into_iter()
+        .map(|(start, node, end)| {
+            let mut nodes = Vec::new();
+            nodes.extend(start.map(Node::Assert));
+            nodes.extend(into_sequence(node));
+            nodes.extend(end.map(Node::Assert));
+            from_sequence(nodes)
+        })
+        .collect();
+
+    Ok(Expression {
+        node: alternation(branches),
+        anchored_start,
+        anchored_end,
+        names: parser.names,
+    })
+}
@@ -0,0 +1,1595 @@
+use crate::check_state_count;
+use crate::compile_expression;
+use crate::error::BudgetExceeded;
+use crate::nfa::Nfa;
+use crate::parse;
+use crate::parse::Expression;
+use crate::parse::Flags;
+use crate::RegexError;
+use crate::DEFAULT_REPETITION_LIMIT;
+use crate::DEFAULT_STATE_LIMIT;
+use std::collections::HashMap;
+
+/// A compiled regular expression.
+///
+/// Compile once with `Regex::new` and reuse it to match as many inputs as needed.
+///
+/// Methods like `is_match` and `find` look for a match anywhere in the text,
+/// unless the pattern starts with `^` or was built with
+/// `RegexBuilder::anchored_start`. `search` looks anywhere even then, unless
+/// the pattern itself starts with `^`, and `is_full_match` only matches the
+/// whole text.
+///
+/// Matching only reads the compiled expression, so a `Regex` is `Send` and `Sync`
+/// and can be shared between threads, for example through an `Arc`.
+pub struct Regex {
+    nfa: Nfa,
+    /// The same expression anchored at both ends.
+    full_nfa: Nfa,
+    /// The same expression anchored at the start.
+    start_nfa: Nfa,
+    /// Whether the expression starts with `^`, so it can only match at the
+    /// start of the text.
+    anchored_start: bool,
+    /// Whether the expression ends with `$`, so it can only match at the
+    /// end of the text.
+    anchored_end: bool,
+    /// The index of each named group.
+    names: HashMap<String, usize>,
+    /// Whether to prefer the longest match, as POSIX does.
+    longest: bool,
+    /// The expression without the start anchor it was given by
+    /// `RegexBuilder::anchored_start`, if it was given one.
+    search_nfa: Option<Nfa>,
+    /// The literal text every match starts with, which is looked for with a
+    /// substring search to skip to where a match could start.
+    prefix: String,
+}
+
+impl Regex {
+    /// Compile a regular expression.
+    pub fn new(pattern: &str) -> Result<Regex, RegexError> {
+        RegexBuilder::new().build(pattern)
+    }
+
+    /// Compile a regular expression that matches letters regardless of case.
+    pub fn new_case_insensitive(pattern: &str) -> Result<Regex, RegexError> {
+        RegexBuilder::new().case_insensitive(true).build(pattern)
+    }
+
+    /// Compile a regular expression where `^` and `$` match at the start and
+    /// end of every line, as well as of the whole text.
+    pub fn new_multiline(pattern: &str) -> Result<Regex, RegexError> {
+        RegexBuilder::new().multi_line(true).build(pattern)
+    }
+
+    /// Compile a regular expression where `.` matches newlines too.
+    pub fn new_dotall(pattern: &str) -> Result<Regex, RegexError> {
+        RegexBuilder::new().dot_all(true).build(pattern)
+    }
+
+    /// Build a regular expression that matches any one of `words`.
+    ///
+    /// The words are matched literally, so characters like `.` don't need
+    /// escaping, and words sharing a prefix share the states that read it.
+    /// Earlier words are preferred when more than one matches at the same
+    /// place. With no words, it never matches.
+    pub fn from_literals(words: &[&str]) -> Regex {
+        Regex::from_expression(parse::literals(words))
+    }
+
+    fn from_expression(mut expression: Expression) -> Regex {
+        let nfa = compile_expression(&expression);
+        let anchored_start = expression.anchored_start;
+        let anchored_end = expression.anchored_end;
+        expression.anchored_start = true;
+        let start_nfa = compile_expression(&expression);
+        expression.anchored_end = true;
+        let full_nfa = compile_expression(&expression);
+        Regex {
+            nfa,
+            full_nfa,
+            start_nfa,
+            anchored_start,
+            anchored_end,
+            names: expression.names,
+            longest: false,
+            search_nfa: None,
+            prefix: expression.node.literal_prefix(),
+        }
+    }
+
+    /// Returns true if the regular expression matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        if self.anchored_start {
+            return self.nfa.run(text);
+        }
+        // a match only looks at the text from where it starts, since it
+        // starts by reading the prefix
+        match self.skip_to_prefix(text, 0) {
+            Some(start) => self.nfa.run(&text[start..]),
+            None => false,
+        }
+    }
+
+    /// Returns true if the regular expression matches anywhere in `text`,
+    /// giving up with `BudgetExceeded` once matching has stepped through more
+    /// than `max_steps` states.
+    ///
+    /// This bounds the time spent on untrusted input, which can otherwise
+    /// grow with the length of the text times the size of the expression.
+    pub fn is_match_with_budget(
+        &self,
+        text: &str,
+        max_steps: usize,
+    ) -> Result<bool, BudgetExceeded> {
+        if self.anchored_start {
+            return self.nfa.run_with_budget(text, max_steps);
+        }
+        match self.skip_to_prefix(text, 0) {
+            Some(start) => self.nfa.run_with_budget(&text[start..], max_steps),
+            None => Ok(false),
+        }
+    }
+
+    /// Find the first place at or after byte offset `from` where the literal
+    /// prefix of every match appears in `text`, if it does.
+    fn skip_to_prefix(&self, text: &str, from: usize) -> Option<usize> {
+        text[from..].find(&self.prefix).map(|start| from + start)
+    }
+
+    /// Returns true if the regular expression matches anywhere in `bytes`,
+    /// which don't need to be valid UTF-8.
+    ///
+    /// Each byte is matched as the character with the same value, so patterns
+    /// written in ASCII work as they would on a string.
+    pub fn is_match_bytes(&self, bytes: &[u8]) -> bool {
+        self.nfa.run_bytes(bytes)
+    }
+
+    /// Returns true if the regular expression matches all of `text`,
+    /// as if it started with `^` and ended with `$`.
+    pub fn is_full_match(&self, text: &str) -> bool {
+        self.full_nfa.run(text)
+    }
+
+    /// Find where the first match in `text` ends, stopping at the earliest
+    /// point where some match is complete rather than extending it.
+    ///
+    /// Returns the byte offset of the end of that match, so `a+` on `"aaa"`
+    /// gives 1 where `find` would give a match ending at 3.
+    pub fn shortest_match(&self, text: &str) -> Option<usize> {
+        if self.anchored_end {
+            // a match can only end where the text does
+            return if self.is_match(text) {
+                Some(text.len())
+            } else {
+                None
+            };
+        }
+        self.nfa.shortest_match(text)
+    }
+
+    /// Match the regular expression starting exactly at byte offset `start`
+    /// of `text`, without skipping any input before the match.
+    ///
+    /// Returns the byte offset where the match ends.
+    /// Anything in `text` before `start` is only looked at by assertions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start` is not on a character boundary of `text`.
+    pub fn matches_at(&self, text: &str, start: usize) -> Option<usize> {
+        assert!(
+            text.is_char_boundary(start),
+            "start is not on a character boundary"
+        );
+        if self.anchored_start && start > 0 {
+            return None;
+        }
+        if self.longest {
+            self.start_nfa.find_anchored_longest_at(text, start)
+        } else {
+            self.start_nfa.find_anchored_at(text, start)
+        }
+    }
+
+    /// Find the first match in `text`.
+    ///
+    /// Returns the byte offsets of the start and end of the match,
+    /// so `&text[start..end]` is the matched substring.
+    pub fn find(&self, text: &str) -> Option<(usize, usize)> {
+        self.find_at(text, 0)
+    }
+
+    /// Find the first match in `text` like `find`, along with whether more
+    /// text after it could still change the match.
+    ///
+    /// This is true when the search reached the end of `text` with paths
+    /// left that could go on reading, so a tokenizer taking the longest match
+    /// can tell a finished token from one it needs more input to finish:
+    /// `\d+` is done with `"12+"`, but might go on after `"12"`. A match that
+    /// ends at the end of `text` can still be finished, as `ab` is on `"ab"`.
+    pub fn find_with_end(&self, text: &str) -> Option<(usize, usize, bool)> {
+        let from = self.start_at(text, 0)?;
+        self.nfa.find_open_at(text, from, self.longest)
+    }
+
+    /// Find the first match anywhere in `text`, even if the `Regex` was built
+    /// with `RegexBuilder::anchored_start`.
+    ///
+    /// A pattern that starts with `^` still only matches at the start of
+    /// the text. For a `Regex` that isn't anchored, this is the same as `find`.
+    pub fn search(&self, text: &str) -> Option<(usize, usize)> {
+        let (nfa, from) = match &self.search_nfa {
+            Some(nfa) => (nfa, self.skip_to_prefix(text, 0)?),
+            None => (&self.nfa, self.start_at(text, 0)?),
+        };
+        if self.longest {
+            nfa.find_longest_at(text, from)
+        } else {
+            nfa.find_at(text, from)
+        }
+    }
+
+    /// Where to start looking for a match that starts at or after byte
+    /// offset `from`, or `None` if there can't be one.
+    fn start_at(&self, text: &str, from: usize) -> Option<usize> {
+        if self.anchored_start {
+            Some(from)
+        } else {
+            self.skip_to_prefix(text, from)
+        }
+    }
+
+    /// Find the first match in `text` that starts at or after byte offset `from`.
+    fn find_at(&self, text: &str, from: usize) -> Option<(usize, usize)> {
+        let from = self.start_at(text, from)?;
+        if self.longest {
+            self.nfa.find_longest_at(text, from)
+        } else {
+            self.nfa.find_at(text, from)
+        }
+    }
+
+    /// Find the first match in `text` along with what each capture group matched.
+    ///
+    /// Index 0 is the whole match and index `n` is the group opened by the
+    /// `n`th `(`, given as byte offsets like `find`. A group that took no part
+    /// in the match, like one that was skipped by a `?`, is `None`.
+    pub fn captures(&self, text: &str) -> Option<Vec<Option<(usize, usize)>>> {
+        self.captures_at(text, 0)
+    }
+
+    /// Find the first match in `text` that starts at or after byte offset
+    /// `from`, along with what each capture group matched.
+    fn captures_at(&self, text: &str, from: usize) -> Option<Vec<Option<(usize, usize)>>> {
+        let from = self.start_at(text, from)?;
+        if self.longest {
+            self.nfa.captures_longest_at(text, from)
+        } else {
+            self.nfa.captures_at(text, from)
+        }
+    }
+
+    /// Find the first match in `text` along with what each named group matched.
+    ///
+    /// Named groups that took no part in the match are left out.
+    pub fn captures_by_name(&self, text: &str) -> Option<HashMap<String, (usize, usize)>> {
+        let groups = self.captures(text)?;
+        Some(
+            self.names
+                .iter()
+                .filter_map(|(name, index)| Some((name.clone(), groups[*index]?)))
+                .collect(),
+        )
+    }
+
+    /// Returns the index of the group called `name`, for use with `captures`.
+    pub fn group_index(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    /// Iterate over every non-overlapping match in `text`, in order.
+    ///
+    /// Each match is given as the byte offsets of its start and end.
+    /// An empty match is followed by skipping a character so that
+    /// the search always moves forward.
+    pub fn find_iter<'r, 't>(&'r self, text: &'t str) -> Matches<'r, 't> {
+        Matches {
+            regex: self,
+            text,
+            position: Some(0),
+        }
+    }
+
+    /// Find the last of the matches `find_iter` finds in `text`.
+    ///
+    /// This is the last of the non-overlapping matches found left to right,
+    /// so `aa` on `"aaaaa"` ends at 4 rather than 5.
+    pub fn find_last(&self, text: &str) -> Option<(usize, usize)> {
+        self.find_iter(text).last()
+    }
+
+    /// Iterate over every non-overlapping match in `text`, in order, along
+    /// with what each capture group matched.
+    ///
+    /// Each item is given like `captures`, and matches are found as
+    /// `find_iter` finds them.
+    pub fn captures_iter<'r, 't>(&'r self, text: &'t str) -> CaptureMatches<'r, 't> {
+        CaptureMatches {
+            regex: self,
+            text,
+            position: Some(0),
+        }
+    }
+
+    /// Count the non-overlapping matches in `text`.
+    ///
+    /// Matches are counted as `find_iter` finds them, including empty ones,
+    /// without keeping their spans.
+    pub fn count_matches(&self, text: &str) -> usize {
+        let mut count = 0;
+        let mut position = Some(0);
+        // only where each match ends is needed to find the next one, so this
+        // follows the paths without the groups `captures` would keep
+        while let Some((start, end)) = position.and_then(|from| self.find_at(text, from)) {
+            count += 1;
+            position = next_position(text, start, end);
+        }
+        count
+    }
+
+    /// Iterate over the match starting at each position of `text`, in order,
+    /// even where the matches overlap.
+    ///
+    /// Each match is the one `matches_at` finds from its start, so `aa` on
+    /// `"aaaa"` gives matches starting at 0, 1 and 2. Since a match is tried
+    /// from every position, this takes time proportional to the length of
+    /// `text` times the length of a match, rather than just the length of `text`.
+    pub fn find_overlapping<'r, 't>(&'r self, text: &'t str) -> OverlappingMatches<'r, 't> {
+        OverlappingMatches {
+            regex: self,
+            text,
+            position: Some(0),
+        }
+    }
+
+    /// Replace the first match in `text` with `replacement`,
+    /// leaving the rest of `text` as it is.
+    ///
+    /// `replacement` can refer to what the capture groups matched, as
+    /// described for `replace_all`.
+    pub fn replace(&self, text: &str, replacement: &str) -> String {
+        match self.captures(text) {
+            Some(groups) => {
+                let (start, end) = groups[0].unwrap();
+                let mut replaced = text[..start].to_string();
+                self.expand(text, &groups, replacement, &mut replaced);
+                replaced.push_str(&text[end..]);
+                replaced
+            }
+            None => text.to_string(),
+        }
+    }
+
+    /// Replace every match in `text` with `replacement`.
+    ///
+    /// In `replacement`, `$n` or `${n}` stands for what group `n` matched and
+    /// `${name}` for what the group called `name` matched, with `$0` being the
+    /// whole match. A group that doesn't exist or took no part in the match
+    /// stands for nothing. `$$` is a literal `$`.
+    pub fn replace_all(&self, text: &str, replacement: &str) -> String {
+        self.replacen(text, usize::MAX, replacement)
+    }
+
+    /// Replace the first `limit` matches in `text` with `replacement`,
+    /// leaving the rest of `text` as it is.
+    ///
+    /// `replacement` can refer to what the capture groups matched, as
+    /// described for `replace_all`.
+    pub fn replacen(&self, text: &str, limit: usize, replacement: &str) -> String {
+        let mut replaced = String::with_capacity(text.len());
+        let mut last_end = 0;
+        for groups in self.captures_iter(text).take(limit) {
+            let (start, end) = groups[0].unwrap();
+            replaced.push_str(&text[last_end..start]);
+            self.expand(text, &groups, replacement, &mut replaced);
+            last_end = end;
+        }
+        replaced.push_str(&text[last_end..]);
+        replaced
+    }
+
+    /// Write `replacement` to `replaced`, with each group reference in it
+    /// replaced by what that group matched in `text`.
+    fn expand(
+        &self,
+        text: &str,
+        groups: &[Option<(usize, usize)>],
+        replacement: &str,
+        replaced: &mut String,
+    ) {
+        let mut rest = replacement;
+        while let Some(dollar) = rest.find('$') {
+            replaced.push_str(&rest[..dollar]);
+            rest = &rest[dollar + 1..];
+
+            if let Some(after) = rest.strip_prefix('$') {
+                replaced.push('$');
+                rest = after;
+                continue;
+            }
+
+            // the group is named in braces, or numbered by the digits that follow
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let reference = match rest.strip_prefix('{').and_then(|r| r.find('}')) {
+                Some(close) => {
+                    let name = &rest[1..close + 1];
+                    rest = &rest[close + 2..];
+                    name
+                }
+                None if digits > 0 => {
+                    let number = &rest[..digits];
+                    rest = &rest[digits..];
+                    number
+                }
+                None => {
+                    // not a reference, so the `$` stands for itself
+                    replaced.push('$');
+                    continue;
+                }
+            };
+
+            let index = reference
+                .parse::<usize>()
+                .ok()
+                .or_else(|| self.group_index(reference));
+            let span = index.and_then(|index| groups.get(index).copied().flatten());
+            if let Some((start, end)) = span {
+                replaced.push_str(&text[start..end]);
+            }
+        }
+        replaced.push_str(rest);
+    }
+
+    /// Split `text` into the pieces between each match.
+    ///
+    /// Like `str::split`, a match at the start or end of `text` gives an empty
+    /// piece there, as do two matches next to each other.
+    pub fn split(&self, text: &str) -> Vec<String> {
+        let mut pieces = Vec::new();
+        let mut last_end = 0;
+        for (start, end) in self.find_iter(text) {
+            pieces.push(text[last_end..start].to_string());
+            last_end = end;
+        }
+        pieces.push(text[last_end..].to_string());
+        pieces
+    }
+}
+
+/// Compiles a `Regex` with flags that change how its pattern is read.
+///
+/// Every flag is off to begin with, which gives the same `Regex` as
+/// `Regex::new`.
+#[derive(Clone)]
+pub struct RegexBuilder {
+    case_insensitive: bool,
+    multi_line: bool,
+    dot_all: bool,
+    graphemes: bool,
+    anchored_start: bool,
+    leftmost_longest: bool,
+    strict_escapes: bool,
+    repetition_limit: usize,
+    state_limit: usize,
+}
+
+impl RegexBuilder {
+    /// Start with every flag off.
+    pub fn new() -> RegexBuilder {
+        RegexBuilder {
+            case_insensitive: false,
+            multi_line: false,
+            dot_all: false,
+            graphemes: false,
+            anchored_start: false,
+            leftmost_longest: false,
+            strict_escapes: false,
+            repetition_limit: DEFAULT_REPETITION_LIMIT,
+            state_limit: DEFAULT_STATE_LIMIT,
+        }
+    }
+
+    /// Match letters regardless of case.
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.case_insensitive = yes;
+        self
+    }
+
+    /// Make `^` and `$` match at the start and end of every line,
+    /// as well as of the whole text.
+    pub fn multi_line(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.multi_line = yes;
+        self
+    }
+
+    /// Make `.` match newlines too.
+    pub fn dot_all(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.dot_all = yes;
+        self
+    }
+
+    /// Make `.` match a character together with the combining marks after it,
+    /// so it reads `e\u{301}` as one `é` rather than leaving the accent.
+    ///
+    /// Only combining marks are joined on, not the other characters that
+    /// Unicode keeps in one grapheme, such as those in emoji sequences.
+    pub fn graphemes(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.graphemes = yes;
+        self
+    }
+
+    /// Only match at the start of the text, as if the pattern began with `^`,
+    /// for validating input rather than searching it. `Regex::search` still
+    /// looks for a match anywhere.
+    pub fn anchored_start(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.anchored_start = yes;
+        self
+    }
+
+    /// Prefer the longest of the matches that start earliest, as POSIX does,
+    /// rather than the one that takes the earliest alternatives. So `a|ab`
+    /// on `"ab"` matches `ab` rather than `a`.
+    ///
+    /// Capture groups are still taken from the earliest alternatives that
+    /// give a match of that length, rather than each being made as long as
+    /// it can be.
+    pub fn leftmost_longest(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.leftmost_longest = yes;
+        self
+    }
+
+    /// Fail with `RegexError::InvalidEscape` on an escape that means nothing,
+    /// like `\q`, rather than matching the character after the `\`.
+    /// Escaped punctuation still matches itself either way.
+    pub fn strict_escapes(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.strict_escapes = yes;
+        self
+    }
+
+    /// Allow counted repetitions to expand to at most `limit` characters,
+    /// rather than `DEFAULT_REPETITION_LIMIT`.
+    pub fn repetition_limit(&mut self, limit: usize) -> &mut RegexBuilder {
+        self.repetition_limit = limit;
+        self
+    }
+
+    /// Fail with `RegexError::TooComplex` if the expression would compile to
+    /// more than `limit` states, rather than `DEFAULT_STATE_LIMIT`.
+    ///
+    /// This counts the states of every copy of the expression the `Regex`
+    /// compiles, such as the one anchored at both ends for `is_full_match`.
+    pub fn state_limit(&mut self, limit: usize) -> &mut RegexBuilder {
+        self.state_limit = limit;
+        self
+    }
+
+    /// Compile `pattern` with the flags set so far.
+    pub fn build(&self, pattern: &str) -> Result<Regex, RegexError> {
+        // the flags are where the expression starts from, so inline flags
+        // can still turn them off
+        let flags = Flags {
+            case_insensitive: self.case_insensitive,
+            multi_line: self.multi_line,
+            dot_all: self.dot_all,
+        };
+        let mut expression =
+            parse::parse(pattern, self.repetition_limit, self.strict_escapes, flags)?;
+        if self.graphemes {
+            expression.node = expression.node.graphemes();
+        }
+        // in multiline mode a written `^` doesn't anchor the expression
+        let search = self.anchored_start && !expression.anchored_start;
+        // besides the expression as it is, the `Regex` keeps it anchored at
+        // the start and at both ends, and anchored by the builder if it is
+        let anchored = if search { 3 } else { 2 };
+        check_state_count(&expression, anchored, self.state_limit)?;
+        let mut search_nfa = None;
+        if search {
+            search_nfa = Some(compile_expression(&expression));
+            expression.anchored_start = true;
+        }
+        let mut regex = Regex::from_expression(expression);
+        if !regex.nfa.can_match() {
+            return Err(RegexError::NeverMatches);
+        }
+        regex.longest = self.leftmost_longest;
+        regex.search_nfa = search_nfa;
+        Ok(regex)
+    }
+}
+
+impl Default for RegexBuilder {
+    fn default() -> RegexBuilder {
+        RegexBuilder::new()
+    }
+}
+
+/// Iterator over the matches of a `Regex`, created by `Regex::find_iter`.
+pub struct Matches<'r, 't> {
+    regex: &'r Regex,
+    text: &'t str,
+    /// Where to search for the next match, if there is anything left to search.
+    position: Option<usize>,
+}
+
+impl<'r, 't> Iterator for Matches<'r, 't> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        let (start, end) = self.regex.find_at(self.text, self.position?)?;
+        self.position = next_position(self.text, start, end);
+        Some((start, end))
+    }
+}
+
+/// Iterator over the matches of a `Regex` and their capture groups,
+/// created by `Regex::captures_iter`.
+pub struct CaptureMatches<'r, 't> {
+    regex: &'r Regex,
+    text: &'t str,
+    /// Where to search for the next match, if there is anything left to search.
+    position: Option<usize>,
+}
+
+impl<'r, 't> Iterator for CaptureMatches<'r, 't> {
+    type Item = Vec<Option<(usize, usize)>>;
+
+    fn next(&mut self) -> Option<Vec<Option<(usize, usize)>>> {
+        let groups = self.regex.captures_at(self.text, self.position?)?;
+        let (start, end) = groups[0].unwrap();
+        self.position = next_position(self.text, start, end);
+        Some(groups)
+    }
+}
+
+/// Where to search for the next match after one from `start` to `end`, if
+/// there is anything left to search.
+fn next_position(text: &str, start: usize, end: usize) -> Option<usize> {
+    if start < end {
+        Some(end)
+    } else {
+        // step past the next character so an empty match isn't found again
+        text[end..].chars().next().map(|c| end + c.len_utf8())
+    }
+}
+
+/// Iterator over the possibly overlapping matches of a `Regex`,
+/// created by `Regex::find_overlapping`.
+pub struct OverlappingMatches<'r, 't> {
+    regex: &'r Regex,
+    text: &'t str,
+    /// Where to try the next match from, if there is anywhere left to try.
+    position: Option<usize>,
+}
+
+impl<'r, 't> Iterator for OverlappingMatches<'r, 't> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        loop {
+            let start = self.position?;
+            self.position = self.text[start..]
+                .chars()
+                .next()
+                .map(|c| start + c.len_utf8());
+            if let Some(end) = self.regex.matches_at(self.text, start) {
+                return Some((start, end));
+            }
+        }
+    }
+}
+
+/// A `&'static Regex` for a pattern written into the program, compiled the
+/// first time it is used and then kept for every use after.
+///
+/// This saves compiling the pattern each time it is needed, as in a function
+/// called in a loop. It can't compile the pattern while the program is being
+/// built; for that, compile an `Nfa` ahead of time and store it with the
+/// `serde` feature, which writes it as its accepting states and a list of
+/// `(from, input, targets)` transitions that can be read back in directly.
+///
+/// # Panics
+/// Panics on first use if the pattern is malformed.
+#[macro_export]
+macro_rules! regex {
+    ($pattern:expr) => {{
+        static REGEX: ::std::sync::OnceLock<$crate::Regex> = ::std::sync::OnceLock::new();
+        REGEX.get_or_init(|| {
+            $crate::Regex::new($pattern)
+                .unwrap_or_else(|error| panic!("invalid regular expression: {}", error))
+        })
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_match_with_budget() {
+        let regex = Regex::new("(a|b)*c").unwrap();
+        assert_eq!(regex.is_match_with_budget("ababc", 1000), Ok(true));
+        assert_eq!(regex.is_match_with_budget("abab", 1000), Ok(false));
+        let long = "ab".repeat(10_000);
+        assert_eq!(regex.is_match_with_budget(&long, 100), Err(BudgetExceeded));
+        // text without the literal prefix is rejected without any steps
+        let regex = Regex::new("xyz").unwrap();
+        assert_eq!(regex.is_match_with_budget(&long, 0), Ok(false));
+    }
+
+    #[test]
+    fn reuse_compiled_regex() {
+        let regex = Regex::new("a+b").unwrap();
+
+        assert!(regex.is_match("aab"));
+        assert!(regex.is_match("xxab"));
+        assert!(!regex.is_match("ba"));
+    }
+
+    #[test]
+    fn from_literals() {
+        let words = ["cart", "car", "dog", "a.b", "(x)", "^$"];
+        let regex = Regex::from_literals(&words);
+        for word in &words {
+            assert!(regex.is_full_match(word), "{}", word);
+        }
+        assert_eq!(regex.find("my cart"), Some((3, 7)));
+        assert_eq!(regex.find("a car"), Some((2, 5)));
+        assert_eq!(regex.find("a.b"), Some((0, 3)));
+        assert!(!regex.is_match("axb"));
+        assert!(!regex.is_match("x ca do"));
+        assert_eq!(regex.count_matches("car cart dog? ^$"), 4);
+
+        assert!(!Regex::from_literals(&[]).is_match(""));
+        assert!(Regex::from_literals(&[""]).is_full_match(""));
+    }
+
+    #[test]
+    fn shortest_match() {
+        assert_eq!(Regex::new("a+").unwrap().shortest_match("aaa"), Some(1));
+        assert_eq!(Regex::new("a+").unwrap().find("aaa"), Some((0, 3)));
+        assert_eq!(Regex::new("a+").unwrap().shortest_match("xxaa"), Some(3));
+        assert_eq!(Regex::new("a*").unwrap().shortest_match("aaa"), Some(0));
+        assert_eq!(Regex::new("a+$").unwrap().shortest_match("aaa"), Some(3));
+        assert_eq!(
+            Regex::new("a+\\b").unwrap().shortest_match("ab aa"),
+            Some(5)
+        );
+        assert_eq!(Regex::new("é").unwrap().shortest_match("aé"), Some(3));
+        assert_eq!(Regex::new("a+").unwrap().shortest_match("bbb"), None);
+    }
+
+    #[test]
+    fn matches_at() {
+        let regex = Regex::new("[a-z]+").unwrap();
+        assert_eq!(regex.matches_at("12abc 3", 2), Some(5));
+        assert_eq!(regex.matches_at("12abc 3", 3), Some(5));
+        // the match would have to skip the digits
+        assert_eq!(regex.matches_at("12abc 3", 0), None);
+        assert_eq!(regex.matches_at("12abc 3", 5), None);
+        assert_eq!(regex.matches_at("12abc 3", 7), None);
+
+        assert_eq!(Regex::new("a*").unwrap().matches_at("ba", 0), Some(0));
+        assert_eq!(Regex::new("a$").unwrap().matches_at("aa", 0), None);
+        assert_eq!(Regex::new("a$").unwrap().matches_at("aa", 1), Some(2));
+        assert_eq!(Regex::new("^a").unwrap().matches_at("aa", 1), None);
+        assert_eq!(Regex::new("\\ba").unwrap().matches_at("aa", 1), None);
+        assert_eq!(Regex::new("\\ba").unwrap().matches_at(" a", 1), Some(2));
+        assert_eq!(
+            Regex::new_multiline("^a").unwrap().matches_at("\na", 1),
+            Some(2)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn matches_at_inside_character() {
+        Regex::new("a").unwrap().matches_at("é", 1);
+    }
+
+    #[test]
+    fn count_matches() {
+        assert_eq!(Regex::new("\\d+").unwrap().count_matches("a1b22c333"), 3);
+        assert_eq!(Regex::new("aa").unwrap().count_matches("aaaaa"), 2);
+        assert_eq!(Regex::new("x").unwrap().count_matches("abc"), 0);
+        // empty matches are counted the same way `find_iter` finds them
+        for (pattern, text) in &[("a*", "baaac"), ("", "abé"), ("\\b", "ab cd")] {
+            let regex = Regex::new(pattern).unwrap();
+            assert_eq!(regex.count_matches(text), regex.find_iter(text).count());
+        }
+        assert_eq!(Regex::new("a*").unwrap().count_matches("baaac"), 4);
+    }
+
+    #[test]
+    fn find_overlapping() {
+        let regex = Regex::new("aa").unwrap();
+        assert_eq!(
+            regex.find_overlapping("aaaa").collect::<Vec<_>>(),
+            vec![(0, 2), (1, 3), (2, 4)]
+        );
+        assert_eq!(
+            regex.find_iter("aaaa").collect::<Vec<_>>(),
+            vec![(0, 2), (2, 4)]
+        );
+
+        let regex = Regex::new("\\d+").unwrap();
+        assert_eq!(
+            regex.find_overlapping("a12é3").collect::<Vec<_>>(),
+            vec![(1, 3), (2, 3), (5, 6)]
+        );
+        assert_eq!(
+            Regex::new("x*")
+                .unwrap()
+                .find_overlapping("éx")
+                .collect::<Vec<_>>(),
+            vec![(0, 0), (2, 3), (3, 3)]
+        );
+        assert_eq!(Regex::new("^a").unwrap().find_overlapping("aa").count(), 1);
+        assert_eq!(Regex::new("b").unwrap().find_overlapping("aa").count(), 0);
+    }
+
+    #[test]
+    fn match_bytes() {
+        let regex = Regex::new("id=\\d+;").unwrap();
+        assert!(regex.is_match_bytes(b"\xff\xfe id=42; \x00"));
+        assert!(!regex.is_match_bytes(b"\xffid=;"));
+        assert!(Regex::new("^a.b$").unwrap().is_match_bytes(b"a\x80b"));
+    }
+
+    #[test]
+    fn full_match() {
+        let regex = Regex::new("a+b").unwrap();
+        assert!(regex.is_full_match("aab"));
+        assert!(!regex.is_full_match("xaab"));
+        assert!(!regex.is_full_match("aabx"));
+        assert!(regex.is_match("xaabx"));
+
+        // anchors that are already there change nothing
+        let regex = Regex::new("^cat|dog$").unwrap();
+        assert!(regex.is_full_match("dog"));
+        assert!(!regex.is_full_match("catdog"));
+        assert!(Regex::new("").unwrap().is_full_match(""));
+        assert!(!Regex::new("").unwrap().is_full_match("a"));
+    }
+
+    #[test]
+    fn multiline() {
+        let regex = Regex::new_multiline("^b").unwrap();
+        assert!(regex.is_match("a\nbc"));
+        assert_eq!(regex.find("a\nbc"), Some((2, 3)));
+        assert!(!Regex::new("^b").unwrap().is_match("a\nbc"));
+
+        let regex = Regex::new_multiline("a$").unwrap();
+        assert!(regex.is_match("ba\nb"));
+        assert!(regex.is_match("a"));
+        assert!(!regex.is_match("ab\nb"));
+
+        let regex = Regex::new_multiline("^\\w+$").unwrap();
+        let lines: Vec<(usize, usize)> = regex.find_iter("one\ntwo three\nfour").collect();
+        assert_eq!(lines, vec![(0, 3), (14, 18)]);
+        assert_eq!(
+            Regex::new_multiline("^$").unwrap().find("a\n\nb"),
+            Some((2, 2))
+        );
+    }
+
+    #[test]
+    fn dotall() {
+        let regex = Regex::new("a.b").unwrap();
+        assert!(regex.is_match("a-b"));
+        assert!(!regex.is_match("a\nb"));
+
+        let regex = Regex::new_dotall("a.b").unwrap();
+        assert!(regex.is_match("a-b"));
+        assert!(regex.is_match("a\nb"));
+        assert_eq!(regex.find("xa\nb"), Some((1, 4)));
+        assert_eq!(Regex::new(".+").unwrap().find("\nab\nc"), Some((1, 3)));
+    }
+
+    #[test]
+    fn graphemes() {
+        // `é` written as `e` and a combining acute accent
+        let text = "ne\u{301}e";
+        let regex = Regex::new("n.e").unwrap();
+        assert!(!regex.is_match(text));
+        assert_eq!(Regex::new("n.").unwrap().find(text), Some((0, 2)));
+
+        let regex = RegexBuilder::new().graphemes(true).build("n.e").unwrap();
+        assert_eq!(regex.find(text), Some((0, 5)));
+        let regex = RegexBuilder::new().graphemes(true).build("^..$").unwrap();
+        assert!(regex.is_match("ne\u{301}"));
+        assert!(!regex.is_match("nee"));
+    }
+
+    #[test]
+    #[allow(clippy::invalid_regex)]
+    fn strict_escapes() {
+        // lenient by default
+        assert!(Regex::new("a\\qb").unwrap().is_match("aqb"));
+        let strict = |pattern: &str| RegexBuilder::new().strict_escapes(true).build(pattern);
+        assert_eq!(
+            strict("a\\qb").err().as_ref(),
+            Some(&RegexError::InvalidEscape(1))
+        );
+        assert_eq!(
+            strict("\\é").err().as_ref(),
+            Some(&RegexError::InvalidEscape(0))
+        );
+        for pattern in &["\\d\\w\\b\\s\\n", "\\.\\*\\$", "a\\-b\\/", "\\p{L}"] {
+            assert!(strict(pattern).is_ok(), "{}", pattern);
+        }
+        assert!(strict("a\\-b").unwrap().is_match("a-b"));
+    }
+
+    #[test]
+    fn inline_flags() {
+        let is_match = |pattern: &str, text: &str| Regex::new(pattern).unwrap().is_match(text);
+        assert!(is_match("(?i)abc", "ABC"));
+        assert!(is_match("(?i)abc", "xAbC"));
+        assert!(is_match("(?i)", "ABC"));
+        // flags apply from where they are set
+        assert!(is_match("^a(?i)bc$", "aBC"));
+        assert!(!is_match("^a(?i)bc$", "ABC"));
+        assert!(is_match("^(?i)a(?-i)b$", "Ab"));
+        assert!(!is_match("^(?i)a(?-i)b$", "AB"));
+        // to the end of the group they are set in, or only inside their own
+        assert!(is_match("^(a(?i)b)c$", "aBc"));
+        assert!(!is_match("^(a(?i)b)c$", "aBC"));
+        assert!(is_match("^(?i:a)b$", "Ab"));
+        assert!(!is_match("^(?i:a)b$", "AB"));
+        // and on through later alternatives
+        assert!(is_match("^x(?i)y|z$", "Z"));
+
+        assert!(is_match("(?s)a.b", "a\nb"));
+        assert!(!is_match("a.b", "a\nb"));
+        assert!(is_match("(?m)^b$", "a\nb\nc"));
+        assert!(!is_match("^b$", "a\nb\nc"));
+        assert!(is_match("(?m)^a|^b", "x\nb"));
+        assert!(is_match("(?is)^A.$", "a\n"));
+        let regex = Regex::new("(?m)^\\d+$").unwrap();
+        assert_eq!(
+            regex.find_iter("12\nab\n3").collect::<Vec<_>>(),
+            [(0, 2), (6, 7)]
+        );
+
+        for (pattern, error) in &[
+            ("(?x)a", RegexError::UnknownFlag(0)),
+            ("a(?)", RegexError::UnknownFlag(1)),
+            ("(?-)a", RegexError::UnknownFlag(0)),
+            ("(?i-:a)", RegexError::UnknownFlag(0)),
+            ("a(?i)*", RegexError::NothingToRepeat(5)),
+            ("(?m){2}a", RegexError::NothingToRepeat(4)),
+            ("(?i", RegexError::UnbalancedParenthesis(0)),
+        ] {
+            assert_eq!(
+                Regex::new(pattern).err().as_ref(),
+                Some(error),
+                "{}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let regex = Regex::new_case_insensitive("abc").unwrap();
+        assert!(regex.is_match("ABC"));
+        assert!(regex.is_match("xAbCx"));
+        assert!(!regex.is_match("ABD"));
+        assert!(!Regex::new("abc").unwrap().is_match("ABC"));
+
+        let regex = Regex::new_case_insensitive("^[a-c]+Z\\d$").unwrap();
+        assert!(regex.is_match("aBcz1"));
+        assert!(regex.is_match("CCZ2"));
+        assert!(!regex.is_match("dz1"));
+
+        let regex = Regex::new_case_insensitive("(straße|ÉtÉ)!").unwrap();
+        assert!(regex.is_match("STRAßE!"));
+        assert!(regex.is_match("été!"));
+        assert_eq!(regex.find("x ÉTÉ!"), Some((2, 8)));
+        // the Kelvin sign has the same lowercase as `K`
+        let regex = Regex::new_case_insensitive("\u{212A}").unwrap();
+        assert!(regex.is_match("k"));
+        assert!(regex.is_match("K"));
+    }
+
+    #[test]
+    fn builder() {
+        let regex = RegexBuilder::new()
+            .case_insensitive(true)
+            .multi_line(true)
+            .build("^ab$")
+            .unwrap();
+        assert!(regex.is_match("x\nAB\ny"));
+        assert!(!regex.is_match("xab"));
+
+        let regex = RegexBuilder::new()
+            .dot_all(true)
+            .case_insensitive(true)
+            .build("^a.B$")
+            .unwrap();
+        assert!(regex.is_match("A\nb"));
+
+        // inline flags can turn off what the builder turned on
+        let mut builder = RegexBuilder::new();
+        builder
+            .case_insensitive(true)
+            .dot_all(true)
+            .multi_line(true);
+        let regex = builder.build("^a(?-i)b$").unwrap();
+        assert!(regex.is_match("Ab"));
+        assert!(!regex.is_match("AB"));
+        assert!(!builder.build("(?-i)a").unwrap().is_match("A"));
+        assert!(builder.build("(?-i:a)b").unwrap().is_match("aB"));
+        assert!(!builder.build("a(?-s).b").unwrap().is_match("a\nb"));
+        assert!(builder.build("a.b").unwrap().is_match("a\nb"));
+        assert!(!builder.build("(?-m)^b$").unwrap().is_match("a\nb"));
+        assert!(builder.build("^b$").unwrap().is_match("a\nb"));
+
+        // no flags is the same as `Regex::new`
+        let regex = RegexBuilder::default().build("^a.b$").unwrap();
+        assert!(!regex.is_match("A\nb"));
+        assert!(regex.is_match("a-b"));
+
+        let unanchored = Regex::new("abc").unwrap();
+        let anchored = RegexBuilder::new()
+            .anchored_start(true)
+            .build("abc")
+            .unwrap();
+        assert!(unanchored.is_match("xabc"));
+        assert!(!anchored.is_match("xabc"));
+        assert!(anchored.is_match("abcx"));
+        assert_eq!(anchored.find("abcabc"), Some((0, 3)));
+        assert_eq!(anchored.matches_at("xabc", 1), None);
+        // still anchored to the whole text in multiline mode
+        let anchored = RegexBuilder::new()
+            .anchored_start(true)
+            .multi_line(true)
+            .build("abc")
+            .unwrap();
+        assert!(!anchored.is_match("x\nabc"));
+
+        let mut builder = RegexBuilder::new();
+        builder.repetition_limit(3);
+        assert!(builder.build("a{3}").is_ok());
+        assert_eq!(
+            builder.build("a{4}").err(),
+            Some(RegexError::RepetitionTooLarge(1))
+        );
+    }
+
+    #[test]
+    fn leftmost_longest() {
+        let first = Regex::new("a|ab").unwrap();
+        let longest = RegexBuilder::new()
+            .leftmost_longest(true)
+            .build("a|ab")
+            .unwrap();
+        assert_eq!(first.find("ab"), Some((0, 1)));
+        assert_eq!(longest.find("ab"), Some((0, 2)));
+        assert_eq!(
+            first.find_iter("abab").collect::<Vec<_>>(),
+            [(0, 1), (2, 3)]
+        );
+        assert_eq!(
+            longest.find_iter("abab").collect::<Vec<_>>(),
+            [(0, 2), (2, 4)]
+        );
+        assert_eq!(first.replace_all("abab", "x"), "xbxb");
+        assert_eq!(longest.replace_all("abab", "x"), "xx");
+        assert_eq!(longest.matches_at("xab", 1), Some(3));
+
+        // only the whole match is made as long as it can be
+        let longest = RegexBuilder::new()
+            .leftmost_longest(true)
+            .build("(foo|foobar)(bar)?")
+            .unwrap();
+        assert_eq!(
+            longest.captures("foobar"),
+            Some(vec![Some((0, 6)), Some((0, 3)), Some((3, 6))])
+        );
+        // both alternatives give the same length, so the first is kept
+        let longest = RegexBuilder::new()
+            .leftmost_longest(true)
+            .build("(a)b|a(b)")
+            .unwrap();
+        assert_eq!(
+            longest.captures("ab"),
+            Some(vec![Some((0, 2)), Some((0, 1)), None])
+        );
+    }
+
+    #[test]
+    fn search() {
+        // searching is the default
+        let regex = Regex::new("abc").unwrap();
+        assert!(regex.is_match("xxxabc"));
+        assert_eq!(regex.find("xxxabc"), Some((3, 6)));
+        assert_eq!(regex.search("xxxabc"), Some((3, 6)));
+        assert!(!regex.is_full_match("xxxabc"));
+
+        // validating only matches at the start, unless asked to search
+        let regex = RegexBuilder::new()
+            .anchored_start(true)
+            .build("abc")
+            .unwrap();
+        assert!(!regex.is_match("xxxabc"));
+        assert!(regex.is_match("abcxxx"));
+        assert_eq!(regex.find("xxxabc"), None);
+        assert_eq!(regex.search("xxxabc"), Some((3, 6)));
+        assert_eq!(regex.search("abcabc"), Some((0, 3)));
+
+        // a written `^` anchors searches too
+        let regex = RegexBuilder::new()
+            .anchored_start(true)
+            .build("^abc")
+            .unwrap();
+        assert_eq!(regex.search("xxxabc"), None);
+        assert_eq!(regex.search("abc"), Some((0, 3)));
+    }
+
+    #[test]
+    fn literal_prefix() {
+        let regex = Regex::new("aab+").unwrap();
+        assert_eq!(regex.prefix, "aa");
+        // the prefix overlaps itself, so its first appearance isn't the match
+        assert!(regex.is_match("xaaab"));
+        assert_eq!(regex.find("xaaab"), Some((2, 5)));
+        assert_eq!(
+            regex.find_iter("aaabaab").collect::<Vec<_>>(),
+            [(1, 4), (4, 7)]
+        );
+        assert!(!regex.is_match("aba ab"));
+
+        let regex = Regex::new("ab\\b").unwrap();
+        assert_eq!(regex.prefix, "ab");
+        assert!(!regex.is_match("abc"));
+        assert_eq!(regex.find("abc ab"), Some((4, 6)));
+
+        for (pattern, prefix) in &[
+            ("abc|abd", "ab"),
+            ("a(b)c", "a"),
+            ("ab*", "a"),
+            ("\\d+ab", ""),
+            ("^abc", ""),
+            ("(?:ab)c", ""),
+        ] {
+            assert_eq!(Regex::new(pattern).unwrap().prefix, *prefix, "{}", pattern);
+        }
+        assert_eq!(Regex::new_case_insensitive("abc").unwrap().prefix, "");
+        assert_eq!(Regex::new_multiline("abc").unwrap().prefix, "abc");
+        assert_eq!(Regex::new_multiline("^abc").unwrap().prefix, "");
+        assert_eq!(Regex::new("^abc").unwrap().find("xabc"), None);
+    }
+
+    #[test]
+    fn case_insensitive_class() {
+        assert!(Regex::new_case_insensitive("[a-f]").unwrap().is_match("D"));
+        assert!(!Regex::new_case_insensitive("[a-f]").unwrap().is_match("G"));
+        assert!(Regex::new_case_insensitive("^[A-C]+$")
+            .unwrap()
+            .is_match("aBc"));
+        // overlapping ranges fold to the same members
+        let regex = Regex::new_case_insensitive("^[a-cA-Cb]$").unwrap();
+        assert!(regex.is_match("B"));
+        assert!(regex.is_match("c"));
+        assert!(!regex.is_match("d"));
+        assert!(Regex::new_case_insensitive("^[é-ê]$")
+            .unwrap()
+            .is_match("Ê"));
+        assert!(Regex::new_case_insensitive("^[xyz-]+$")
+            .unwrap()
+            .is_match("X-Y"));
+    }
+
+    #[test]
+    fn share_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Regex>();
+        assert_send_sync::<crate::Nfa>();
+        assert_send_sync::<crate::Dfa>();
+
+        let regex = std::sync::Arc::new(Regex::new("^(\\d+)-\\d+$").unwrap());
+        let workers: Vec<_> = (0..4)
+            .map(|i| {
+                let regex = std::sync::Arc::clone(&regex);
+                std::thread::spawn(move || {
+                    (0..100).all(|j| {
+                        let text = format!("{}-{}", i, j);
+                        regex.is_match(&text) && regex.captures(&text).unwrap()[1] == Some((0, 1))
+                    })
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            assert!(worker.join().unwrap());
+        }
+    }
+
+    #[test]
+    fn find() {
+        let regex = Regex::new("bcd").unwrap();
+        assert_eq!(regex.find("aabcdxx"), Some((2, 5)));
+        assert_eq!(regex.find("aabcxx"), None);
+
+        let regex = Regex::new("a+").unwrap();
+        assert_eq!(regex.find("baaab"), Some((1, 4)));
+
+        let regex = Regex::new("cd$").unwrap();
+        assert_eq!(regex.find("cdcd"), Some((2, 4)));
+        assert_eq!(Regex::new("").unwrap().find("abc"), Some((0, 0)));
+    }
+
+    #[test]
+    fn find_prefers_earliest_start() {
+        let regex = Regex::new("ab").unwrap();
+        assert_eq!(regex.find("aab"), Some((1, 3)));
+
+        let regex = Regex::new("x*y").unwrap();
+        assert_eq!(regex.find("axxy"), Some((1, 4)));
+    }
+
+    #[test]
+    fn captures() {
+        let regex = Regex::new("(\\d+)-(\\d+)").unwrap();
+        let text = "ab 12-34";
+        let groups = regex.captures(text).unwrap();
+        assert_eq!(groups, vec![Some((3, 8)), Some((3, 5)), Some((6, 8))]);
+        assert_eq!(&text[3..5], "12");
+        assert_eq!(&text[6..8], "34");
+        assert_eq!(regex.captures("12-"), None);
+
+        // groups are numbered by where they open, even when nested
+        let regex = Regex::new("((a)|(b))+c").unwrap();
+        assert_eq!(
+            regex.captures("xabc").unwrap(),
+            vec![Some((1, 4)), Some((2, 3)), Some((1, 2)), Some((2, 3))]
+        );
+
+        // a group skipped by `?` took no part in the match
+        let regex = Regex::new("a(x)?b").unwrap();
+        assert_eq!(regex.captures("ab").unwrap(), vec![Some((0, 2)), None]);
+        assert_eq!(
+            regex.captures("axb").unwrap(),
+            vec![Some((0, 3)), Some((1, 2))]
+        );
+        // taking the group is preferred to skipping it
+        assert_eq!(
+            Regex::new("(x)?x*").unwrap().captures("xx").unwrap(),
+            vec![Some((0, 2)), Some((0, 1))]
+        );
+        assert_eq!(
+            Regex::new("ab").unwrap().captures("ab"),
+            Some(vec![Some((0, 2))])
+        );
+    }
+
+    #[test]
+    #[allow(clippy::regex_creation_in_loops)]
+    fn captures_are_reproducible() {
+        let expected = Regex::new("(a|ab)(c|bcd)(d*)").unwrap().captures("abcd");
+        assert_eq!(
+            expected,
+            Some(vec![Some((0, 4)), Some((0, 1)), Some((1, 4)), Some((4, 4))])
+        );
+        for _ in 0..20 {
+            let regex = Regex::new("(a|ab)(c|bcd)(d*)").unwrap();
+            assert_eq!(regex.captures("abcd"), expected);
+        }
+    }
+
+    #[test]
+    fn non_capturing_group() {
+        let regex = Regex::new("(?:x)(y)").unwrap();
+        assert_eq!(
+            regex.captures("xy").unwrap(),
+            vec![Some((0, 2)), Some((1, 2))]
+        );
+
+        let regex = Regex::new("^(?:ab)+$").unwrap();
+        assert!(regex.is_match("abab"));
+        assert!(!regex.is_match("aba"));
+        assert_eq!(regex.captures("ab").unwrap(), vec![Some((0, 2))]);
+    }
+
+    #[test]
+    fn named_captures() {
+        let regex = Regex::new("(?P<year>\\d{4})-(\\d\\d)-(?P<day>\\d\\d)?").unwrap();
+        let text = "on 2024-05-17";
+        let groups = regex.captures_by_name(text).unwrap();
+        let (start, end) = groups["year"];
+        assert_eq!(&text[start..end], "2024");
+        assert_eq!(groups["day"], (11, 13));
+        assert_eq!(regex.group_index("year"), Some(1));
+        assert_eq!(regex.group_index("day"), Some(3));
+        assert_eq!(regex.group_index("month"), None);
+
+        // the unnamed group is still counted
+        assert_eq!(regex.captures(text).unwrap()[2], Some((8, 10)));
+        assert!(!regex
+            .captures_by_name("2024-05-")
+            .unwrap()
+            .contains_key("day"));
+    }
+
+    #[test]
+    #[allow(clippy::invalid_regex)]
+    fn invalid_group_name() {
+        for (pattern, position) in &[
+            ("(?P<>a)", 0),
+            ("(?P<1a>a)", 0),
+            ("(?P<a-b>a)", 0),
+            ("(?P<a", 0),
+            ("(?P<a>x)(?P<a>y)", 8),
+        ] {
+            assert_eq!(
+                Regex::new(pattern).err(),
+                Some(RegexError::InvalidGroupName(*position)),
+                "pattern {:?}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn find_multibyte() {
+        let regex = Regex::new("é+").unwrap();
+        assert_eq!(regex.find("caféé!"), Some((3, 7)));
+    }
+
+    #[test]
+    fn spans_land_on_character_boundaries() {
+        let text = "héllo 👋 wörld";
+        let regex = Regex::new("l+o").unwrap();
+        let (start, end) = regex.find(text).unwrap();
+        assert_eq!(&text[start..end], "llo");
+        assert_eq!((start, end), (3, 6));
+
+        let regex = Regex::new("(\\w+) (.) (\\w+)").unwrap();
+        let groups = regex.captures(text).unwrap();
+        let pieces: Vec<&str> = groups
+            .iter()
+            .map(|group| {
+                let (start, end) = group.unwrap();
+                &text[start..end]
+            })
+            .collect();
+        // `\w` only takes ASCII word characters, so `h` and `w` are left out
+        assert_eq!(pieces, ["llo 👋 w", "llo", "👋", "w"]);
+
+        let words: Vec<&str> = Regex::new("\\S+")
+            .unwrap()
+            .find_iter(text)
+            .map(|(start, end)| &text[start..end])
+            .collect();
+        assert_eq!(words, ["héllo", "👋", "wörld"]);
+        for (start, _) in Regex::new("").unwrap().find_iter(text) {
+            assert!(text.is_char_boundary(start));
+        }
+    }
+
+    #[test]
+    fn find_iter() {
+        let regex = Regex::new("\\d+").unwrap();
+        let matches: Vec<_> = regex.find_iter("a1b22c333").collect();
+        assert_eq!(matches, vec![(1, 2), (3, 5), (6, 9)]);
+
+        let regex = Regex::new("aa").unwrap();
+        let matches: Vec<_> = regex.find_iter("aaaaa").collect();
+        assert_eq!(matches, vec![(0, 2), (2, 4)]);
+    }
+
+    #[test]
+    fn find_iter_large_input() {
+        // each search stops once its match is decided, rather than reading
+        // on to the end of the text
+        let text = "a1".repeat(100_000);
+        let started = std::time::Instant::now();
+        assert_eq!(Regex::new("\\d").unwrap().find_iter(&text).count(), 100_000);
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    }
+
+    #[test]
+    fn find_with_end() {
+        let regex = Regex::new("\\d+").unwrap();
+        assert_eq!(regex.find_with_end("12+3"), Some((0, 2, false)));
+        assert_eq!(regex.find_with_end("12"), Some((0, 2, true)));
+        assert_eq!(regex.find_with_end("x12"), Some((1, 3, true)));
+        assert_eq!(regex.find_with_end("x"), None);
+        // ending at the end of the text doesn't mean more could follow
+        let regex = Regex::new("ab").unwrap();
+        assert_eq!(regex.find_with_end("xab"), Some((1, 3, false)));
+        assert_eq!(
+            Regex::new("a\\d?").unwrap().find_with_end("a"),
+            Some((0, 1, true))
+        );
+        // a preferred branch that could still match changes the answer
+        assert_eq!(
+            Regex::new("ab|a").unwrap().find_with_end("a"),
+            Some((0, 1, true))
+        );
+        assert_eq!(
+            Regex::new("a|ab").unwrap().find_with_end("a"),
+            Some((0, 1, false))
+        );
+        assert_eq!(
+            RegexBuilder::new()
+                .leftmost_longest(true)
+                .build("a|ab")
+                .unwrap()
+                .find_with_end("a"),
+            Some((0, 1, true))
+        );
+        // an empty match at the end moves if more text follows
+        assert_eq!(
+            Regex::new("$").unwrap().find_with_end("ab"),
+            Some((2, 2, true))
+        );
+    }
+
+    #[test]
+    fn find_last() {
+        assert_eq!(
+            Regex::new("\\d+").unwrap().find_last("a1b2c3"),
+            Some((5, 6))
+        );
+        assert_eq!(Regex::new("\\d+").unwrap().find_last("a12b"), Some((1, 3)));
+        assert_eq!(Regex::new("\\d+").unwrap().find_last("abc"), None);
+        assert_eq!(Regex::new("aa").unwrap().find_last("aaaaa"), Some((2, 4)));
+        assert_eq!(Regex::new("x*").unwrap().find_last("ab"), Some((2, 2)));
+    }
+
+    #[test]
+    fn captures_iter() {
+        let regex = Regex::new("(\\d+)x(\\d+)").unwrap();
+        let text = "sizes: 3x4, 1920x1080 and 10x2";
+        let sizes: Vec<(&str, &str)> = regex
+            .captures_iter(text)
+            .map(|groups| {
+                let (width, height) = (groups[1].unwrap(), groups[2].unwrap());
+                (&text[width.0..width.1], &text[height.0..height.1])
+            })
+            .collect();
+        assert_eq!(sizes, [("3", "4"), ("1920", "1080"), ("10", "2")]);
+
+        // empty matches are stepped past like in `find_iter`
+        let regex = Regex::new("(a)?").unwrap();
+        let groups: Vec<_> = regex.captures_iter("ba").collect();
+        assert_eq!(
+            groups,
+            [
+                vec![Some((0, 0)), None],
+                vec![Some((1, 2)), Some((1, 2))],
+                vec![Some((2, 2)), None],
+            ]
+        );
+        assert_eq!(regex.captures_iter("ba").count(), regex.count_matches("ba"));
+    }
+
+    #[test]
+    fn find_iter_empty_matches() {
+        let regex = Regex::new("a*").unwrap();
+        let matches: Vec<_> = regex.find_iter("baab").collect();
+        assert_eq!(matches, vec![(0, 0), (1, 3), (3, 3), (4, 4)]);
+
+        let matches: Vec<_> = regex.find_iter("").collect();
+        assert_eq!(matches, vec![(0, 0)]);
+    }
+
+    #[test]
+    fn find_iter_anchored() {
+        let regex = Regex::new("^a").unwrap();
+        let matches: Vec<_> = regex.find_iter("aaa").collect();
+        assert_eq!(matches, vec![(0, 1)]);
+    }
+
+    #[test]
+    fn replace() {
+        let regex = Regex::new("a+").unwrap();
+        assert_eq!(regex.replace("aa bb aa", "X"), "X bb aa");
+        assert_eq!(regex.replace("bb aaa", "X"), "bb X");
+        assert_eq!(regex.replace("bbb", "X"), "bbb");
+        assert_eq!(Regex::new("x*").unwrap().replace("ab", "-"), "-ab");
+    }
+
+    #[test]
+    fn replace_all() {
+        let regex = Regex::new("a+").unwrap();
+        assert_eq!(regex.replace_all("baaab aa", "X"), "bXb X");
+        assert_eq!(regex.replace_all("bbb", "X"), "bbb");
+
+        let regex = Regex::new("x*").unwrap();
+        assert_eq!(regex.replace_all("ab", "-"), "-a-b-");
+    }
+
+    #[test]
+    fn regex_macro() {
+        fn word() -> &'static Regex {
+            crate::regex!("\\w+(?:-\\w+)*")
+        }
+        let regex = Regex::new("\\w+(?:-\\w+)*").unwrap();
+        for text in &["", "well-known fact", "--", "a-b-c d"] {
+            assert_eq!(word().find(text), regex.find(text), "{:?}", text);
+            assert_eq!(
+                word().find_iter(text).collect::<Vec<_>>(),
+                regex.find_iter(text).collect::<Vec<_>>()
+            );
+        }
+        // compiled only once
+        assert!(std::ptr::eq(word(), word()));
+    }
+
+    #[test]
+    fn replacen() {
+        let regex = Regex::new("a+").unwrap();
+        let text = "baaab aa a";
+        assert_eq!(regex.replacen(text, 0, "X"), text);
+        assert_eq!(regex.replacen(text, 2, "X"), "bXb X a");
+        assert_eq!(regex.replacen(text, 3, "X"), "bXb X X");
+        assert_eq!(regex.replacen(text, 10, "X"), "bXb X X");
+        assert_eq!(regex.replacen("(ab)", 1, "[$0]"), "([a]b)");
+    }
+
+    #[test]
+    fn replace_with_group_references() {
+        let regex = Regex::new("(\\w+)@(\\w+)").unwrap();
+        assert_eq!(regex.replace_all("user@host", "$2.$1"), "host.user");
+        assert_eq!(regex.replace_all("a@b, c@d", "${2}x$1"), "bxa, dxc");
+        assert_eq!(regex.replace("a@b c@d", "[$0]"), "[a@b] c@d");
+        // missing groups stand for nothing
+        assert_eq!(
+            regex.replace_all("a@b", "$3${9}$99999999999999999999|"),
+            "|"
+        );
+        assert_eq!(regex.replace_all("a@b", "$$1 costs $"), "$1 costs $");
+        assert_eq!(regex.replace_all("a@b", "${1"), "${1");
+
+        let regex = Regex::new("(?P<first>\\w+) (?P<last>\\w+)(!)?").unwrap();
+        assert_eq!(
+            regex.replace_all("Ada Lovelace", "${last}, ${first}${3}"),
+            "Lovelace, Ada"
+        );
+        assert_eq!(regex.replace_all("Ada Lovelace", "${nobody}"), "");
+        assert_eq!(regex.replace_all("Ada Lovelace!", "$3$3"), "!!");
+
+        // empty matches move on the same way as `find_iter`
+        assert_eq!(
+            Regex::new("(x*)").unwrap().replace_all("ab", "<$1>"),
+            "<>a<>b<>"
+        );
+    }
+
+    #[test]
+    fn split() {
+        let regex = Regex::new("\\d+").unwrap();
+        assert_eq!(regex.split("a1b22c"), vec!["a", "b", "c"]);
+        assert_eq!(regex.split("1a2"), vec!["", "a", ""]);
+        assert_eq!(regex.split("abc"), vec!["abc"]);
+
+        let regex = Regex::new(",").unwrap();
+        assert_eq!(regex.split("a,,b"), vec!["a", "", "b"]);
+    }
+
+    #[test]
+    #[allow(clippy::invalid_regex)]
+    fn invalid_regex() {
+        assert_eq!(
+            Regex::new("(a").err(),
+            Some(RegexError::UnbalancedParenthesis(0))
+        );
+    }
+}